@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
 
 use clap::Parser;
 
@@ -8,6 +8,10 @@ use crate::build_info;
 #[derive(Parser)]
 #[command(version = build_info::version_formatted())]
 pub struct Arguments {
+    /// Path to TOML or JSON configuration file
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
     /// Path to main Lua file
     #[arg(short, long)]
     pub script: Option<PathBuf>,
@@ -15,4 +19,24 @@ pub struct Arguments {
     /// Code to execute (after script)
     #[arg(short, long)]
     pub exec: Option<String>,
+
+    /// Server to connect to (overrides configuration)
+    #[arg(long)]
+    pub server: Option<String>,
+
+    /// Account to log in with (overrides configuration)
+    #[arg(long)]
+    pub username: Option<String>,
+
+    /// Address for the HTTP server to listen on (overrides configuration)
+    #[arg(long)]
+    pub http_address: Option<SocketAddr>,
+
+    /// Start an interactive Lua REPL on stdin
+    #[arg(short, long)]
+    pub repl: bool,
+
+    /// Replay an event journal into the script's listeners instead of connecting
+    #[arg(long)]
+    pub replay_journal: Option<PathBuf>,
 }
@@ -0,0 +1,215 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use azalea::Client;
+use log::warn;
+use mlua::{Lua, Table};
+use parking_lot::Mutex;
+
+const MAX_QUEUED: usize = 64;
+
+/// Outgoing chat messages, sent at the rate `ChatOptions` allows.
+#[derive(Clone, Default)]
+pub struct ChatQueue(Arc<Mutex<Inner>>);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    High,
+    Normal,
+}
+
+#[derive(Default)]
+struct Inner {
+    high: VecDeque<String>,
+    normal: VecDeque<String>,
+    sent: VecDeque<(Instant, String)>,
+}
+
+struct Options {
+    messages: usize,
+    interval: Duration,
+    duplicate_window: Duration,
+}
+
+impl Options {
+    fn get(lua: &Lua) -> Self {
+        let options = lua.globals().get::<Table>("ChatOptions").ok();
+        let seconds = |key: &str, default: f64| {
+            Duration::from_secs_f64(
+                options
+                    .as_ref()
+                    .and_then(|options| options.get::<f64>(key).ok())
+                    .unwrap_or(default)
+                    .max(0.0),
+            )
+        };
+        Self {
+            messages: options
+                .as_ref()
+                .and_then(|options| options.get::<usize>("messages").ok())
+                .unwrap_or(5),
+            interval: seconds("interval", 5.0),
+            duplicate_window: seconds("duplicate_window", 0.0),
+        }
+    }
+}
+
+impl ChatQueue {
+    /// Queues a message, returning false if it was dropped.
+    pub fn push(&self, lua: &Lua, message: String, priority: Priority) -> bool {
+        let window = Options::get(lua).duplicate_window;
+        let mut inner = self.0.lock();
+        if priority == Priority::Normal
+            && (inner.high.contains(&message)
+                || inner.normal.contains(&message)
+                || inner
+                    .sent
+                    .iter()
+                    .any(|(time, sent)| *sent == message && time.elapsed() < window))
+        {
+            return false;
+        }
+
+        if inner.high.len() + inner.normal.len() >= MAX_QUEUED {
+            let dropped = match priority {
+                Priority::High => inner.normal.pop_front().or_else(|| inner.high.pop_front()),
+                Priority::Normal => inner.normal.pop_front(),
+            };
+            let Some(dropped) = dropped else {
+                return false;
+            };
+            warn!("chat queue is full, dropping message: {dropped}");
+        }
+        match priority {
+            Priority::High => inner.high.push_back(message),
+            Priority::Normal => inner.normal.push_back(message),
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        let inner = self.0.lock();
+        inner.high.len() + inner.normal.len()
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.0.lock();
+        inner.high.clear();
+        inner.normal.clear();
+    }
+
+    /// Sends as many waiting messages as `ChatOptions.messages` per `ChatOptions.interval` allows.
+    pub fn flush(&self, client: &Client, lua: &Lua) {
+        if self.len() == 0 {
+            return;
+        }
+        for message in self.take(lua) {
+            client.chat(&message);
+        }
+    }
+
+    fn take(&self, lua: &Lua) -> Vec<String> {
+        let options = Options::get(lua);
+        let mut inner = self.0.lock();
+        let keep = options.interval.max(options.duplicate_window);
+        while inner
+            .sent
+            .front()
+            .is_some_and(|(time, _)| time.elapsed() >= keep)
+        {
+            inner.sent.pop_front();
+        }
+
+        let recent = inner
+            .sent
+            .iter()
+            .filter(|(time, _)| time.elapsed() < options.interval)
+            .count();
+        let mut messages = Vec::new();
+        while recent + messages.len() < options.messages {
+            let Some(message) = inner.high.pop_front().or_else(|| inner.normal.pop_front()) else {
+                break;
+            };
+            inner.sent.push_back((Instant::now(), message.clone()));
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_options(options: &str) -> Lua {
+        let lua = Lua::new();
+        lua.load(format!("ChatOptions = {options}")).exec().unwrap();
+        lua
+    }
+
+    #[test]
+    fn sends_high_priority_first() {
+        let lua = with_options("{ messages = 10 }");
+        let queue = ChatQueue::default();
+        assert!(queue.push(&lua, String::from("a"), Priority::Normal));
+        assert!(queue.push(&lua, String::from("b"), Priority::High));
+        assert!(queue.push(&lua, String::from("c"), Priority::Normal));
+        assert!(queue.push(&lua, String::from("d"), Priority::High));
+        assert_eq!(queue.take(&lua), ["b", "d", "a", "c"]);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn suppresses_duplicates() {
+        let lua = with_options("{ messages = 10, duplicate_window = 60 }");
+        let queue = ChatQueue::default();
+        assert!(queue.push(&lua, String::from("hi"), Priority::Normal));
+        assert!(!queue.push(&lua, String::from("hi"), Priority::Normal));
+        assert!(queue.push(&lua, String::from("hi"), Priority::High));
+        assert_eq!(queue.take(&lua), ["hi", "hi"]);
+        assert!(!queue.push(&lua, String::from("hi"), Priority::Normal));
+        assert!(queue.push(&lua, String::from("hi"), Priority::High));
+    }
+
+    #[test]
+    fn caps_the_queue() {
+        let lua = with_options("{ messages = 100 }");
+        let queue = ChatQueue::default();
+        for i in 0..MAX_QUEUED {
+            assert!(queue.push(&lua, i.to_string(), Priority::Normal));
+        }
+        assert!(queue.push(&lua, String::from("new"), Priority::Normal));
+        assert!(queue.push(&lua, String::from("reply"), Priority::High));
+        assert_eq!(queue.len(), MAX_QUEUED);
+        let messages = queue.take(&lua);
+        assert_eq!(messages.first().unwrap(), "reply");
+        assert_eq!(messages[1], "2");
+        assert_eq!(messages.last().unwrap(), "new");
+
+        queue.clear();
+        for i in 0..MAX_QUEUED {
+            assert!(queue.push(&lua, i.to_string(), Priority::High));
+        }
+        assert!(!queue.push(&lua, String::from("dropped"), Priority::Normal));
+        assert!(queue.push(&lua, String::from("kept"), Priority::High));
+        assert_eq!(queue.len(), MAX_QUEUED);
+    }
+
+    #[test]
+    fn limits_messages_per_interval() {
+        let lua = with_options("{ messages = 2, interval = 60 }");
+        let queue = ChatQueue::default();
+        for message in ["a", "b", "c"] {
+            queue.push(&lua, String::from(message), Priority::Normal);
+        }
+        assert_eq!(queue.take(&lua), ["a", "b"]);
+        assert!(queue.take(&lua).is_empty());
+        assert_eq!(queue.len(), 1);
+
+        let lua = with_options("{ messages = 2, interval = 0 }");
+        assert_eq!(queue.take(&lua), ["c"]);
+    }
+}
@@ -1,108 +1,427 @@
-use azalea::{brigadier::prelude::*, chat::ChatPacket, prelude::*};
+use std::{collections::HashSet, fmt::Write};
+
+use azalea::{
+    brigadier::{
+        errors::{BuiltInError, CommandSyntaxError},
+        prelude::*,
+    },
+    chat::ChatPacket,
+};
 use futures::lock::Mutex;
-use mlua::{Error, Result, Table, UserDataRef};
+use mlua::{Error, Lua, Result, Table, UserDataRef};
 use ncr::{
     encoding::{Base64Encoding, Base64rEncoding, NewBase64rEncoding},
     encryption::{CaesarEncryption, Cfb8Encryption, EcbEncryption, Encryption, GcmEncryption},
     utils::prepend_header,
 };
+#[cfg(feature = "matrix")]
+use {
+    log::error,
+    matrix_sdk::{
+        Room,
+        ruma::{OwnedUserId, events::room::message::RoomMessageEventContent},
+    },
+};
 
 use crate::{
-    State, crypt,
-    lua::{eval, exec, nochatreports::key::AesKey, reload},
+    ListenerMap, State,
+    chat::Priority,
+    crypt,
+    events::emit,
+    lua::{Frontend, Sender, eval, exec, nochatreports::key::AesKey, reload},
+    permissions,
 };
 
 pub type Ctx = CommandContext<Mutex<CommandSource>>;
 
 pub struct CommandSource {
-    pub client: Client,
-    pub message: ChatPacket,
     pub state: State,
-    pub ncr_options: Option<Table>,
+    pub origin: Origin,
+    allowed: HashSet<String>,
+}
+
+/// Where a command came from, which decides who is running it and where replies go.
+pub enum Origin {
+    Chat {
+        message: ChatPacket,
+        ncr_options: Option<Table>,
+        whisper: bool,
+    },
+    #[cfg(feature = "matrix")]
+    Matrix { room: Room, sender: OwnedUserId },
 }
 
 impl CommandSource {
-    pub fn reply(&self, message: &str) {
+    pub fn new(state: State, origin: Origin) -> Self {
+        let mut source = Self {
+            state,
+            origin,
+            allowed: HashSet::new(),
+        };
+        // resolved up front, since brigadier checks permissions while holding the command tree
+        // lock, which would deadlock against registering a command from lua
+        if let Some(sender) = source.sender() {
+            source.allowed = permissions::allowed_commands(&source.state.lua, &sender);
+        }
+        source
+    }
+
+    pub fn sender(&self) -> Option<Sender> {
+        match &self.origin {
+            Origin::Chat { message, .. } => Some(Sender {
+                name: message.sender()?,
+                uuid: message.sender_uuid().map(|uuid| uuid.to_string()),
+                frontend: Frontend::Chat,
+            }),
+            #[cfg(feature = "matrix")]
+            Origin::Matrix { sender, .. } => Some(Sender {
+                name: sender.to_string(),
+                uuid: None,
+                frontend: Frontend::Matrix,
+            }),
+        }
+    }
+
+    pub fn is_allowed(&self, command: &str) -> bool {
+        permissions::contains(&self.allowed, command)
+    }
+
+    pub fn reply(&self, reply: &str) {
         fn encrypt(options: &Table, plaintext: &str) -> Result<String> {
             Ok(crypt!(encrypt, options, &prepend_header(plaintext)))
         }
 
-        for mut chunk in message
-            .chars()
-            .collect::<Vec<char>>()
-            .chunks(if self.ncr_options.is_some() { 150 } else { 236 })
-            .map(|chars| chars.iter().collect::<String>())
-        {
-            if let Some(ciphertext) = self
-                .ncr_options
-                .as_ref()
-                .and_then(|options| encrypt(options, &chunk).ok())
-            {
-                chunk = ciphertext;
-            }
-            self.client.chat(
-                &(if self.message.is_whisper()
-                    && let Some(username) = self.message.sender()
+        match &self.origin {
+            Origin::Chat {
+                message,
+                ncr_options,
+                whisper,
+            } => {
+                for mut chunk in reply
+                    .chars()
+                    .collect::<Vec<char>>()
+                    .chunks(if ncr_options.is_some() { 150 } else { 236 })
+                    .map(|chars| chars.iter().collect::<String>())
                 {
-                    format!("/w {username} {chunk}")
-                } else {
-                    chunk
-                }),
-            );
+                    if let Some(ciphertext) = ncr_options
+                        .as_ref()
+                        .and_then(|options| encrypt(options, &chunk).ok())
+                    {
+                        chunk = ciphertext;
+                    }
+                    self.state.chat.push(
+                        &self.state.lua,
+                        if *whisper && let Some(username) = message.sender() {
+                            format!("/w {username} {chunk}")
+                        } else {
+                            chunk
+                        },
+                        Priority::High,
+                    );
+                }
+            }
+            #[cfg(feature = "matrix")]
+            Origin::Matrix { room, .. } => {
+                let room = room.clone();
+                let content = RoomMessageEventContent::text_html(
+                    reply,
+                    format!("<pre><code>{reply}</code></pre>"),
+                );
+                tokio::spawn(async move {
+                    if let Err(error) = room.send(content).await {
+                        error!("failed to reply in {}: {error:?}", room.room_id());
+                    }
+                });
+            }
         }
     }
 }
 
 pub fn register(commands: &mut CommandDispatcher<Mutex<CommandSource>>) {
-    commands.register(literal("reload").executes(|ctx: &Ctx| {
-        let source = ctx.source.clone();
-        tokio::spawn(async move {
-            let source = source.lock().await;
-            source.reply(
-                &reload(&source.state.lua, source.message.sender())
-                    .map_or_else(|error| error.to_string(), |()| String::from("ok")),
-            );
-        });
-        1
-    }));
-
     commands.register(
-        literal("eval").then(argument("code", string()).executes(|ctx: &Ctx| {
+        literal("reload")
+            .requires(allowed("reload"))
+            .executes(|ctx: &Ctx| {
+                let source = ctx.source.clone();
+                tokio::spawn(async move {
+                    let source = source.lock().await;
+                    source.reply(
+                        &reload(&source.state.lua, source.sender())
+                            .map_or_else(|error| error.to_string(), |()| String::from("ok")),
+                    );
+                });
+                1
+            }),
+    );
+
+    commands.register(literal("eval").requires(allowed("eval")).then(
+        argument("code", string()).executes(|ctx: &Ctx| {
             let source = ctx.source.clone();
             let code = get_string(ctx, "code").expect("argument should exist");
             tokio::spawn(async move {
                 let source = source.lock().await;
                 source.reply(
-                    &eval(&source.state.lua, &code, source.message.sender())
+                    &eval::<String>(&source.state.lua, &code, source.sender())
                         .await
                         .unwrap_or_else(|error| error.to_string()),
                 );
             });
             1
-        })),
-    );
+        }),
+    ));
 
-    commands.register(
-        literal("exec").then(argument("code", string()).executes(|ctx: &Ctx| {
+    commands.register(literal("exec").requires(allowed("exec")).then(
+        argument("code", string()).executes(|ctx: &Ctx| {
             let source = ctx.source.clone();
             let code = get_string(ctx, "code").expect("argument should exist");
             tokio::spawn(async move {
                 let source = source.lock().await;
                 source.reply(
-                    &exec(&source.state.lua, &code, source.message.sender())
+                    &exec(&source.state.lua, &code, source.sender())
                         .await
                         .map_or_else(|error| error.to_string(), |()| String::from("ok")),
                 );
             });
             1
-        })),
+        }),
+    ));
+
+    commands.register(
+        literal("emit").requires(allowed("emit")).then(
+            argument("type", word())
+                .executes(|ctx: &Ctx| {
+                    emit_event(ctx, String::new());
+                    1
+                })
+                .then(argument("data", greedy_string()).executes(|ctx: &Ctx| {
+                    emit_event(ctx, get_string(ctx, "data").expect("argument should exist"));
+                    1
+                })),
+        ),
     );
 
-    commands.register(literal("ping").executes(|ctx: &Ctx| {
-        let source = ctx.source.clone();
-        tokio::spawn(async move {
-            source.lock().await.reply("pong!");
+    commands.register(
+        literal("help")
+            .requires(allowed("help"))
+            .executes(|ctx: &Ctx| {
+                help(ctx, None);
+                1
+            })
+            .then(argument("command", word()).executes(|ctx: &Ctx| {
+                help(ctx, get_string(ctx, "command"));
+                1
+            })),
+    );
+
+    commands.register(
+        literal("ping")
+            .requires(allowed("ping"))
+            .executes(|ctx: &Ctx| {
+                let source = ctx.source.clone();
+                tokio::spawn(async move {
+                    source.lock().await.reply("pong!");
+                });
+                1
+            }),
+    );
+}
+
+fn emit_event(ctx: &Ctx, data: String) {
+    let source = ctx.source.clone();
+    let event_type = get_string(ctx, "type").expect("argument should exist");
+    tokio::spawn(async move {
+        let source = source.lock().await;
+        source.reply(
+            &emit(&source.state, None, &event_type, &data)
+                .await
+                .map_or_else(|error| error.to_string(), |()| String::from("ok")),
+        );
+    });
+}
+
+/// Returns the command in a public chat message that starts with `CommandOptions.prefix`.
+pub fn strip_prefix(lua: &Lua, content: &str) -> Option<String> {
+    let prefix = lua
+        .globals()
+        .get::<Table>("CommandOptions")
+        .and_then(|options| options.get::<String>("prefix"))
+        .ok()?;
+    if prefix.trim().is_empty() {
+        return None;
+    }
+    let command = content.strip_prefix(prefix.as_str())?;
+    // a prefix like "!bot" shouldn't match "!botany"
+    if prefix.ends_with(char::is_alphanumeric)
+        && !command.is_empty()
+        && !command.starts_with(char::is_whitespace)
+    {
+        return None;
+    }
+    Some(command.trim_start().to_owned())
+}
+
+/// Returns the part of a public `command` meant for the bot called `name`, if any.
+pub fn addressed_to(bots: &[(String, ListenerMap)], name: &str, command: &str) -> Option<String> {
+    let (first, rest) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    if let Some((bot, _)) = bots.iter().find(|(bot, _)| bot.eq_ignore_ascii_case(first)) {
+        return (bot == name).then(|| rest.trim_start().to_owned());
+    }
+    bots.first()
+        .is_none_or(|(bot, _)| bot == name)
+        .then(|| command.to_owned())
+}
+
+/// Whether replies to `command` from public chat should be whispered.
+pub fn whispers_reply(lua: &Lua, command: &str) -> bool {
+    let Ok(options) = lua.globals().get::<Table>("CommandOptions") else {
+        return true;
+    };
+    let name = command.split_whitespace().next().unwrap_or_default();
+    options
+        .get::<Table>("replies")
+        .and_then(|replies| replies.get::<String>(name))
+        .or_else(|_| options.get::<String>("reply"))
+        .map_or(true, |mode| mode != "public")
+}
+
+fn help(ctx: &Ctx, command: Option<String>) {
+    let source = ctx.source.clone();
+    tokio::spawn(async move {
+        let commands = source.lock().await.state.commands.clone();
+        let usages = usages(&commands, &source, command.as_deref());
+        source.lock().await.reply(&if usages.is_empty() {
+            format!("unknown command {}", command.unwrap_or_default())
+        } else {
+            usages.join(", ")
         });
-        1
-    }));
+    });
+}
+
+fn usages(
+    commands: &CommandDispatcher<Mutex<CommandSource>>,
+    source: &Mutex<CommandSource>,
+    command: Option<&str>,
+) -> Vec<String> {
+    commands
+        .get_smart_usage(&commands.root.read(), source)
+        .into_iter()
+        .filter(|(node, _)| command.is_none_or(|command| node.read().name() == command))
+        .map(|(_, usage)| usage)
+        .collect()
+}
+
+/// Describes why `input` failed to run, if `source` should be told.
+pub fn describe_error(
+    commands: &CommandDispatcher<Mutex<CommandSource>>,
+    source: &Mutex<CommandSource>,
+    input: &str,
+    error: &CommandSyntaxError,
+) -> Option<String> {
+    let command = input.split_whitespace().next().unwrap_or_default();
+    if *error.kind() == BuiltInError::DispatcherUnknownCommand {
+        return (!usages(commands, source, None).is_empty())
+            .then(|| format!("unknown command {command}, try help"));
+    }
+
+    let mut message = error.raw_message().clone();
+    if let Some(context) = error.context() {
+        let _ = write!(message, " at {context}");
+    }
+    let usages = usages(commands, source, Some(command));
+    if !usages.is_empty() {
+        let _ = write!(message, " (usage: {})", usages.join(", "));
+    }
+    Some(message)
+}
+
+pub fn allowed(command: &str) -> impl Fn(&Mutex<CommandSource>) -> bool + Send + Sync + use<> {
+    let command = command.to_owned();
+    move |source| {
+        source
+            .try_lock()
+            .is_some_and(|source| source.is_allowed(&command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_options(options: &str) -> Lua {
+        let lua = Lua::new();
+        lua.load(format!("CommandOptions = {options}"))
+            .exec()
+            .unwrap();
+        lua
+    }
+
+    #[test]
+    fn strips_prefix() {
+        let lua = with_options("{ prefix = '!' }");
+        assert_eq!(strip_prefix(&lua, "!help"), Some(String::from("help")));
+        assert_eq!(
+            strip_prefix(&lua, "! help me"),
+            Some(String::from("help me"))
+        );
+        assert_eq!(strip_prefix(&lua, "help"), None);
+        assert_eq!(strip_prefix(&Lua::new(), "!help"), None);
+    }
+
+    #[test]
+    fn strips_word_prefix() {
+        let lua = with_options("{ prefix = '!bot' }");
+        assert_eq!(strip_prefix(&lua, "!bot help"), Some(String::from("help")));
+        assert_eq!(strip_prefix(&lua, "!bot"), Some(String::new()));
+        assert_eq!(strip_prefix(&lua, "!botany"), None);
+    }
+
+    #[test]
+    fn ignores_empty_prefix() {
+        assert_eq!(strip_prefix(&with_options("{ prefix = '' }"), "help"), None);
+        assert_eq!(
+            strip_prefix(&with_options("{ prefix = ' ' }"), " help"),
+            None
+        );
+    }
+
+    #[test]
+    fn addresses_one_bot() {
+        let bots = [
+            (String::from("Alpha"), ListenerMap::default()),
+            (String::from("Beta"), ListenerMap::default()),
+        ];
+        assert_eq!(
+            addressed_to(&bots, "Alpha", "ping"),
+            Some(String::from("ping"))
+        );
+        assert_eq!(addressed_to(&bots, "Beta", "ping"), None);
+        assert_eq!(
+            addressed_to(&bots, "Beta", "beta exec print(1)"),
+            Some(String::from("exec print(1)"))
+        );
+        assert_eq!(addressed_to(&bots, "Alpha", "Beta exec print(1)"), None);
+        assert_eq!(addressed_to(&bots, "Beta", "Beta"), Some(String::new()));
+        assert_eq!(
+            addressed_to(&[], "Alpha", "ping"),
+            Some(String::from("ping"))
+        );
+    }
+
+    #[test]
+    fn chooses_reply_mode() {
+        assert!(whispers_reply(&Lua::new(), "help"));
+        assert!(whispers_reply(&with_options("{}"), "help"));
+
+        let lua =
+            with_options("{ reply = 'public', replies = { secret = 'whisper', help = 'public' } }");
+        assert!(!whispers_reply(&lua, "help"));
+        assert!(!whispers_reply(&lua, "ping now"));
+        assert!(whispers_reply(&lua, "secret code"));
+
+        let lua = with_options("{ reply = 'whisper', replies = { ping = 'public' } }");
+        assert!(!whispers_reply(&lua, "ping"));
+        assert!(whispers_reply(&lua, "help"));
+    }
 }
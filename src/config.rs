@@ -0,0 +1,335 @@
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use log::debug;
+use mlua::{Lua, LuaSerdeExt, Table, Value};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{arguments::Arguments, lua::nochatreports::key::AesKey};
+
+const DEFAULT_PATHS: [&str; 2] = ["errornowatcher.toml", "errornowatcher.json"];
+
+/// Startup configuration read from a TOML or JSON file and applied as Lua globals.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    server: Option<String>,
+    username: Option<String>,
+    accounts: Option<Vec<String>>,
+    http_address: Option<SocketAddr>,
+    chat: Option<ChatOptions>,
+    command: Option<CommandOptions>,
+    journal: Option<JournalOptions>,
+    listener: Option<ListenerOptions>,
+    owners: Option<Vec<String>>,
+    matrix: Option<MatrixOptions>,
+    ncr: Option<NcrOptions>,
+    permissions: Option<Permissions>,
+    reconnect: Option<ReconnectOptions>,
+    replay_recording: Option<ReplayRecordingOptions>,
+    sequential_events: Option<Vec<String>>,
+    watch: Option<WatchOptions>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ChatOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    messages: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interval: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_window: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct CommandOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply: Option<ReplyMode>,
+    #[serde(default)]
+    replies: HashMap<String, ReplyMode>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ReplyMode {
+    Public,
+    Whisper,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct JournalOptions {
+    path: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ListenerOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_failures: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coalesce_ticks: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct MatrixOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    homeserver_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sync_timeout: Option<u64>,
+    #[serde(default)]
+    owners: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NcrOptions {
+    #[serde(default)]
+    encryption: u8,
+    #[serde(default)]
+    encoding: u8,
+    key: Option<String>,
+    passphrase: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Permissions {
+    #[serde(default)]
+    roles: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    minecraft: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    matrix: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    http: HashMap<String, HttpPrincipal>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct HttpPrincipal {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret_file: Option<String>,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ReconnectOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delay: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_delay: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    multiplier: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jitter: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ReplayRecordingOptions {
+    path: String,
+    #[serde(default)]
+    ignore_compression: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct WatchOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debounce: Option<u64>,
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+impl Config {
+    /// Loads the file at `path`, or the first of the default paths that exists.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match DEFAULT_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+            {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+        };
+        debug!("loading configuration from {}", path.display());
+
+        let contents =
+            read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let config: Self = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            serde_json::from_str(&contents).map_err(anyhow::Error::from)
+        } else {
+            toml::from_str(&contents).map_err(anyhow::Error::from)
+        }
+        .with_context(|| format!("invalid configuration in {}", path.display()))?;
+
+        if let Some(ncr) = &config.ncr
+            && ncr.encryption != 1
+            && ncr.key.is_none()
+            && ncr.passphrase.is_none()
+        {
+            bail!(
+                "invalid configuration in {}: ncr needs a key or passphrase",
+                path.display()
+            );
+        }
+        Ok(config)
+    }
+
+    /// Sets the global for every option in the file, before the script runs.
+    pub fn apply(&self, lua: &Lua, globals: &Table) -> Result<()> {
+        for (name, value) in self.globals(lua)? {
+            globals.set(name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Fills in the options from the file that the script left nil, after the script runs.
+    pub fn merge(&self, lua: &Lua, globals: &Table) -> Result<()> {
+        for (name, value) in self.globals(lua)? {
+            match (globals.get::<Value>(name)?, value) {
+                (Value::Nil, value) => globals.set(name, value)?,
+                (Value::Table(current), Value::Table(value)) => {
+                    for pair in value.pairs::<Value, Value>() {
+                        let (key, value) = pair?;
+                        if current.get::<Value>(&key)?.is_nil() {
+                            current.set(key, value)?;
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn globals(&self, lua: &Lua) -> Result<Vec<(&'static str, Value)>> {
+        let mut globals = Vec::new();
+        macro_rules! set {
+            ($field:ident, $global:literal) => {
+                if let Some(value) = &self.$field {
+                    globals.push(($global, lua.to_value(value)?));
+                }
+            };
+        }
+
+        set!(server, "Server");
+        set!(username, "Username");
+        set!(accounts, "Accounts");
+        set!(chat, "ChatOptions");
+        set!(command, "CommandOptions");
+        set!(journal, "JournalOptions");
+        set!(listener, "ListenerOptions");
+        set!(owners, "Owners");
+        set!(matrix, "MatrixOptions");
+        set!(permissions, "Permissions");
+        set!(reconnect, "ReconnectOptions");
+        set!(replay_recording, "ReplayRecordingOptions");
+        set!(sequential_events, "SequentialEvents");
+        set!(watch, "WatchOptions");
+        if let Some(address) = self.http_address {
+            globals.push(("HttpAddress", lua.to_value(&address.to_string())?));
+        }
+
+        if let Some(ncr) = &self.ncr {
+            let options = lua.create_table()?;
+            options.set("encryption", ncr.encryption)?;
+            options.set("encoding", ncr.encoding)?;
+            if let Some(key) = &ncr.key {
+                options.set(
+                    "key",
+                    AesKey(
+                        ncr::AesKey::decode_base64(key)
+                            .map_err(|error| anyhow!("invalid ncr key: {error}"))?,
+                    ),
+                )?;
+            } else if let Some(passphrase) = &ncr.passphrase {
+                options.set(
+                    "key",
+                    AesKey(ncr::AesKey::gen_from_passphrase(passphrase.as_bytes())),
+                )?;
+            }
+            globals.push(("NcrOptions", Value::Table(options)));
+        }
+        Ok(globals)
+    }
+}
+
+/// Applies command line overrides, which take precedence over both the file and the script.
+pub fn apply_arguments(args: &Arguments, globals: &Table) -> Result<()> {
+    if let Some(server) = &args.server {
+        globals.set("Server", server.as_str())?;
+    }
+    if let Some(username) = &args.username {
+        globals.set("Username", username.as_str())?;
+        globals.set("Accounts", Value::Nil)?;
+    }
+    if let Some(address) = args.http_address {
+        globals.set("HttpAddress", address.to_string())?;
+    }
+    Ok(())
+}
+
+/// Checks the final configuration globals against the same schema as the file.
+pub fn validate(lua: &Lua, globals: &Table) -> Result<()> {
+    fn check<T: DeserializeOwned>(lua: &Lua, globals: &Table, name: &str) -> Result<Option<T>> {
+        lua.from_value(globals.get::<Value>(name)?)
+            .with_context(|| format!("invalid {name}"))
+    }
+
+    check::<String>(lua, globals, "Server")?.context("lua globals missing Server variable")?;
+    if check::<String>(lua, globals, "Username")?.is_none()
+        && check::<Vec<String>>(lua, globals, "Accounts")?.is_none()
+    {
+        bail!("lua globals missing Username or Accounts variable");
+    }
+    check::<SocketAddr>(lua, globals, "HttpAddress")?;
+    check::<ChatOptions>(lua, globals, "ChatOptions")?;
+    if check::<CommandOptions>(lua, globals, "CommandOptions")?
+        .and_then(|options| options.prefix)
+        .is_some_and(|prefix| prefix.trim().is_empty())
+    {
+        bail!("CommandOptions.prefix can't be empty");
+    }
+    check::<JournalOptions>(lua, globals, "JournalOptions")?;
+    check::<ListenerOptions>(lua, globals, "ListenerOptions")?;
+    check::<Vec<String>>(lua, globals, "Owners")?;
+    check::<MatrixOptions>(lua, globals, "MatrixOptions")?;
+    check::<Permissions>(lua, globals, "Permissions")?;
+    check::<ReconnectOptions>(lua, globals, "ReconnectOptions")?;
+    check::<ReplayRecordingOptions>(lua, globals, "ReplayRecordingOptions")?;
+    check::<Vec<String>>(lua, globals, "SequentialEvents")?;
+    check::<WatchOptions>(lua, globals, "WatchOptions")?;
+    Ok(())
+}
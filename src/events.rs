@@ -1,14 +1,25 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use azalea::{
-    brigadier::errors::BuiltInError, prelude::*, protocol::packets::game::ClientboundGamePacket,
+    entity::{LookDirection, metadata::AbstractLiving},
+    prelude::*,
+    protocol::packets::{ProtocolPacket, game::ClientboundGamePacket},
+    swarm::prelude::*,
 };
+use futures::{lock::Mutex, poll};
 use hyper::{server::conn::http1, service::service_fn};
 use hyper_util::rt::TokioIo;
-use log::{debug, error, info, trace};
-use mlua::{Function, IntoLuaMulti, Table};
+use log::{debug, error, info, trace, warn};
+use mlua::{Function, IntoLuaMulti, Lua, LuaSerdeExt, MultiValue, Table, Value};
 use ncr::utils::trim_header;
+use serde_json::Value as JsonValue;
 use tokio::net::TcpListener;
 #[cfg(feature = "matrix")]
 use {crate::matrix, std::time::Duration, tokio::time::sleep};
@@ -16,13 +27,64 @@ use {crate::matrix, std::time::Duration, tokio::time::sleep};
 use {crate::replay::recorder::Recorder, anyhow::Context, mlua::Error, std::process::exit};
 
 use crate::{
-    State,
-    commands::CommandSource,
-    http::serve,
-    lua::{client, direction::Direction, player::Player, vec3::Vec3},
-    particle,
+    ListenerMap, State,
+    commands::{self, CommandSource, Origin},
+    http::{serve, stream::data_to_json},
+    lua::{
+        client,
+        container::item_stack::ItemStack,
+        direction::Direction,
+        events::{Listener, take_listeners},
+        metadata::EntityMetadata,
+        packet,
+        player::Player,
+        vec3::Vec3,
+    },
+    particle, permissions, reconnect, watcher,
 };
 
+const HOT_EVENTS: [&str; 2] = ["tick", "entity_move"];
+const BUILTIN_EVENTS: [&str; 38] = [
+    "add_entity",
+    "add_player",
+    "block_destruction",
+    "block_event",
+    "block_update",
+    "chat",
+    "connection_failed",
+    "death",
+    "disconnect",
+    "entity_damage",
+    "entity_equipment",
+    "entity_hurt",
+    "entity_metadata",
+    "entity_move",
+    "entity_status",
+    "entity_teleport",
+    "explosion",
+    "init",
+    "keep_alive",
+    "level_particles",
+    "listener_disabled",
+    "login",
+    "matrix_chat",
+    "matrix_init",
+    "matrix_join_room",
+    "receive_chunk",
+    "reconnected",
+    "reconnecting",
+    "reload_failed",
+    "remove_entities",
+    "remove_player",
+    "section_blocks_update",
+    "set_health",
+    "set_passengers",
+    "set_time",
+    "spawn",
+    "tick",
+    "update_player",
+];
+
 #[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
 pub async fn handle_event(client: Client, event: Event, state: State) -> Result<()> {
     match event {
@@ -56,30 +118,33 @@ pub async fn handle_event(client: Client, event: Event, state: State) -> Result<
                     info!("decrypted message from {sender}: {content}");
                 }
 
-                if is_whisper
-                    && globals
-                        .get::<Vec<String>>("Owners")
-                        .unwrap_or_default()
-                        .contains(sender)
-                    && let Err(error) = state.commands.execute(
-                        content.clone(),
-                        CommandSource {
-                            client: client.clone(),
-                            message: message.clone(),
-                            state: state.clone(),
-                            ncr_options: ncr_options.clone(),
+                let command = if is_whisper {
+                    Some(content.clone())
+                } else {
+                    commands::strip_prefix(&state.lua, &content).and_then(|command| {
+                        commands::addressed_to(&state.bots, &state.name, &command)
+                    })
+                };
+                if let Some(command) = command {
+                    let whisper = is_whisper || commands::whispers_reply(&state.lua, &command);
+                    let source = || {
+                        CommandSource::new(
+                            state.clone(),
+                            Origin::Chat {
+                                message: message.clone(),
+                                ncr_options: ncr_options.clone(),
+                                whisper,
+                            },
+                        )
+                    };
+                    if let Err(error) = state.commands.execute(command.clone(), source().into()) {
+                        let source = Mutex::new(source());
+                        if let Some(reply) =
+                            commands::describe_error(&state.commands, &source, &command, &error)
+                        {
+                            source.into_inner().reply(&reply);
                         }
-                        .into(),
-                    )
-                    && *error.kind() != BuiltInError::DispatcherUnknownCommand
-                {
-                    CommandSource {
-                        client,
-                        message,
-                        state: state.clone(),
-                        ncr_options,
                     }
-                    .reply(&format!("{error:?}"));
                 }
             }
 
@@ -98,7 +163,8 @@ pub async fn handle_event(client: Client, event: Event, state: State) -> Result<
             .await
         }
         Event::ConnectionFailed(error) => {
-            call_listeners(&state, "connection_failed", || Ok(error.to_string())).await
+            call_listeners(&state, "connection_failed", || Ok(error.to_string())).await?;
+            reconnect::schedule(client, &state, Some(error.to_string())).await
         }
         Event::Death(packet) => {
             if let Some(packet) = packet {
@@ -118,7 +184,7 @@ pub async fn handle_event(client: Client, event: Event, state: State) -> Result<
             }
         }
         Event::Disconnect(message) => {
-            if let Some(message) = message {
+            if let Some(ref message) = message {
                 call_listeners(&state, "disconnect", || {
                     let table = state.lua.create_table()?;
                     table.set("text", message.to_string())?;
@@ -126,111 +192,387 @@ pub async fn handle_event(client: Client, event: Event, state: State) -> Result<
                     table.set("html_text", message.to_html())?;
                     Ok(table)
                 })
-                .await
+                .await?;
             } else {
-                call_listeners(&state, "disconnect", || Ok(())).await
+                call_listeners(&state, "disconnect", || Ok(())).await?;
             }
+            state.chat.clear();
+            reconnect::schedule(client, &state, message.map(|message| message.to_string())).await
         }
         Event::KeepAlive(id) => call_listeners(&state, "keep_alive", || Ok(id)).await,
-        Event::ReceiveChunk(_) => Ok(()),
+        Event::ReceiveChunk(position) => {
+            call_listeners(&state, "receive_chunk", || {
+                let table = state.lua.create_table()?;
+                table.set("x", position.x)?;
+                table.set("z", position.z)?;
+                Ok(table)
+            })
+            .await
+        }
         Event::RemovePlayer(player_info) => {
             call_listeners(&state, "remove_player", || Ok(Player::from(player_info))).await
         }
         Event::Spawn => call_listeners(&state, "spawn", || Ok(())).await,
-        Event::Tick => call_listeners(&state, "tick", || Ok(())).await,
+        Event::Tick => {
+            state.chat.flush(&client, &state.lua);
+            if state.ticking.load(Ordering::Acquire)
+                && state
+                    .lua
+                    .globals()
+                    .get::<Table>("ListenerOptions")
+                    .and_then(|options| options.get::<bool>("coalesce_ticks"))
+                    .unwrap_or_default()
+            {
+                trace!("skipping tick, lua is still handling the last one");
+                return Ok(());
+            }
+            let Some((listeners, data)) = prepare_event(&state, "tick", || Ok(())).await? else {
+                return Ok(());
+            };
+
+            state.ticking.store(true, Ordering::Release);
+            tokio::spawn(async move {
+                let _ticking = Ticking(state.ticking.clone());
+                call_all(
+                    state.lua.as_ref().clone(),
+                    state.event_listeners.clone(),
+                    String::from("tick"),
+                    listeners,
+                    data,
+                    false,
+                )
+                .await;
+            });
+            Ok(())
+        }
         Event::UpdatePlayer(player_info) => {
             call_listeners(&state, "update_player", || Ok(Player::from(player_info))).await
         }
-        Event::Packet(packet) => match packet.as_ref() {
-            ClientboundGamePacket::AddEntity(packet) => {
-                call_listeners(&state, "add_entity", || {
-                    let table = state.lua.create_table()?;
-                    table.set("id", packet.id.0)?;
-                    table.set("uuid", packet.uuid.to_string())?;
-                    table.set("kind", packet.entity_type.to_string())?;
-                    table.set("position", Vec3::from(packet.position))?;
-                    table.set(
-                        "direction",
-                        Direction {
-                            y: f32::from(packet.y_rot) / (256.0 / 360.0),
-                            x: f32::from(packet.x_rot) / (256.0 / 360.0),
-                        },
-                    )?;
-                    table.set("data", packet.data)?;
-                    Ok(table)
-                })
-                .await
-            }
-            ClientboundGamePacket::LevelParticles(packet) => {
-                call_listeners(&state, "level_particles", || {
-                    let table = state.lua.create_table()?;
-                    table.set("position", Vec3::from(packet.pos))?;
-                    table.set("count", packet.count)?;
-                    table.set("kind", particle::to_kind(&packet.particle) as u8)?;
-                    Ok(table)
-                })
-                .await
-            }
-            ClientboundGamePacket::RemoveEntities(packet) => {
-                call_listeners(&state, "remove_entities", || {
-                    Ok(packet.entity_ids.iter().map(|id| id.0).collect::<Vec<_>>())
-                })
-                .await
-            }
-            ClientboundGamePacket::SetHealth(packet) => {
-                call_listeners(&state, "set_health", || {
-                    let table = state.lua.create_table()?;
-                    table.set("food", packet.food)?;
-                    table.set("health", packet.health)?;
-                    table.set("saturation", packet.saturation)?;
-                    Ok(table)
-                })
-                .await
-            }
-            ClientboundGamePacket::SetPassengers(packet) => {
-                call_listeners(&state, "set_passengers", || {
-                    let table = state.lua.create_table()?;
-                    table.set("vehicle", *packet.vehicle)?;
-                    table.set(
-                        "passengers",
-                        packet.passengers.iter().map(|id| id.0).collect::<Vec<_>>(),
-                    )?;
-                    Ok(table)
-                })
-                .await
-            }
-            ClientboundGamePacket::SetTime(packet) => {
-                call_listeners(&state, "set_time", || {
-                    let table = state.lua.create_table()?;
-                    table.set("game_time", packet.game_time)?;
-                    Ok(table)
-                })
-                .await
+        Event::Packet(packet) => {
+            call_packet_listeners(&state, &packet).await?;
+            match packet.as_ref() {
+                ClientboundGamePacket::AddEntity(packet) => {
+                    call_listeners(&state, "add_entity", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.id.0)?;
+                        table.set("uuid", packet.uuid.to_string())?;
+                        table.set("kind", packet.entity_type.to_string())?;
+                        table.set("position", Vec3::from(packet.position))?;
+                        table.set(
+                            "direction",
+                            Direction {
+                                y: f32::from(packet.y_rot) / (256.0 / 360.0),
+                                x: f32::from(packet.x_rot) / (256.0 / 360.0),
+                            },
+                        )?;
+                        table.set("data", packet.data)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::BlockDestruction(packet) => {
+                    call_listeners(&state, "block_destruction", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.id.0)?;
+                        table.set("position", Vec3::from(packet.pos))?;
+                        table.set("progress", packet.progress)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::BlockEvent(packet) => {
+                    call_listeners(&state, "block_event", || {
+                        let table = state.lua.create_table()?;
+                        table.set("position", Vec3::from(packet.pos))?;
+                        table.set("action_id", packet.action_id)?;
+                        table.set("action_parameter", packet.action_parameter)?;
+                        table.set("block", packet.block.to_string())?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::BlockUpdate(packet) => {
+                    call_listeners(&state, "block_update", || {
+                        let table = state.lua.create_table()?;
+                        table.set("position", Vec3::from(packet.pos))?;
+                        table.set("state", packet.block_state.id())?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::DamageEvent(packet) => {
+                    call_listeners(&state, "entity_damage", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.entity_id.0)?;
+                        table.set("source_type", packet.source_type_id)?;
+                        table.set("source_cause", packet.source_cause_id.0)?;
+                        table.set("source_direct", packet.source_direct_id.0)?;
+                        table.set("source_position", packet.source_position.map(Vec3::from))?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::EntityEvent(packet) => {
+                    call_listeners(&state, "entity_status", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.entity_id.0)?;
+                        table.set("status", packet.event_id)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::EntityPositionSync(packet) => {
+                    call_listeners(&state, "entity_teleport", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.id.0)?;
+                        table.set("position", Vec3::from(packet.values.pos))?;
+                        table.set("velocity", Vec3::from(packet.values.delta))?;
+                        table.set("direction", Direction::from(&packet.values.look_direction))?;
+                        table.set("on_ground", packet.on_ground)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::Explode(packet) => {
+                    call_listeners(&state, "explosion", || {
+                        let table = state.lua.create_table()?;
+                        table.set("position", Vec3::from(packet.center))?;
+                        table.set("radius", packet.radius)?;
+                        table.set("block_count", packet.block_count)?;
+                        table.set("knockback", packet.player_knockback.map(Vec3::from))?;
+                        table.set(
+                            "particle",
+                            particle::to_kind(&packet.explosion_particle) as u8,
+                        )?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::HurtAnimation(packet) => {
+                    call_listeners(&state, "entity_hurt", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.id.0)?;
+                        table.set("yaw", packet.yaw)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::LevelParticles(packet) => {
+                    call_listeners(&state, "level_particles", || {
+                        let table = state.lua.create_table()?;
+                        table.set("position", Vec3::from(packet.pos))?;
+                        table.set("count", packet.count)?;
+                        table.set("kind", particle::to_kind(&packet.particle) as u8)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::MoveEntityPos(packet) => {
+                    call_listeners(&state, "entity_move", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.entity_id.0)?;
+                        table.set("delta", Vec3::from(&packet.delta))?;
+                        table.set("on_ground", packet.on_ground)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::MoveEntityPosRot(packet) => {
+                    call_listeners(&state, "entity_move", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.entity_id.0)?;
+                        table.set("delta", Vec3::from(&packet.delta))?;
+                        table.set(
+                            "direction",
+                            Direction::from(&LookDirection::from(packet.look_direction)),
+                        )?;
+                        table.set("on_ground", packet.on_ground)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::MoveEntityRot(packet) => {
+                    call_listeners(&state, "entity_move", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.entity_id.0)?;
+                        table.set(
+                            "direction",
+                            Direction::from(&LookDirection::from(packet.look_direction)),
+                        )?;
+                        table.set("on_ground", packet.on_ground)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::RemoveEntities(packet) => {
+                    call_listeners(&state, "remove_entities", || {
+                        Ok(packet.entity_ids.iter().map(|id| id.0).collect::<Vec<_>>())
+                    })
+                    .await
+                }
+                ClientboundGamePacket::RotateHead(packet) => {
+                    call_listeners(&state, "entity_move", || {
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.entity_id.0)?;
+                        table.set("head_yaw", f32::from(packet.y_head_rot) / (256.0 / 360.0))?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::SectionBlocksUpdate(packet) => {
+                    call_listeners(&state, "section_blocks_update", || {
+                        let blocks = state.lua.create_table()?;
+                        for block in &packet.states {
+                            let table = state.lua.create_table()?;
+                            table.set("position", Vec3::from(packet.section_pos + block.pos))?;
+                            table.set("state", block.state.id())?;
+                            blocks.push(table)?;
+                        }
+                        Ok(blocks)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::SetEntityData(packet) => {
+                    call_listeners(&state, "entity_metadata", || {
+                        let is_living =
+                            client
+                                .entity_id_by_minecraft_id(packet.id)
+                                .is_some_and(|entity| {
+                                    client.ecs.read().get::<AbstractLiving>(entity).is_some()
+                                });
+                        Ok(EntityMetadata {
+                            id: packet.id.0,
+                            items: packet.packed_items.0.clone(),
+                            is_living,
+                        })
+                    })
+                    .await
+                }
+                ClientboundGamePacket::SetEquipment(packet) => {
+                    call_listeners(&state, "entity_equipment", || {
+                        let equipment = state.lua.create_table()?;
+                        let items = state.lua.create_table()?;
+                        for (slot, item_stack) in &packet.slots.slots {
+                            let slot = format!("{slot:?}").to_lowercase();
+                            let item_stack = ItemStack(item_stack.clone());
+                            items.set(slot.as_str(), item_stack.to_table(&state.lua)?)?;
+                            equipment.set(slot, item_stack)?;
+                        }
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.entity_id.0)?;
+                        table.set("equipment", equipment)?;
+                        table.set("items", items)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::SetHealth(packet) => {
+                    call_listeners(&state, "set_health", || {
+                        let table = state.lua.create_table()?;
+                        table.set("food", packet.food)?;
+                        table.set("health", packet.health)?;
+                        table.set("saturation", packet.saturation)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::SetPassengers(packet) => {
+                    call_listeners(&state, "set_passengers", || {
+                        let table = state.lua.create_table()?;
+                        table.set("vehicle", *packet.vehicle)?;
+                        table.set(
+                            "passengers",
+                            packet.passengers.iter().map(|id| id.0).collect::<Vec<_>>(),
+                        )?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::SetTime(packet) => {
+                    call_listeners(&state, "set_time", || {
+                        let table = state.lua.create_table()?;
+                        table.set("game_time", packet.game_time)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                ClientboundGamePacket::TeleportEntity(packet) => {
+                    call_listeners(&state, "entity_teleport", || {
+                        let relative = state.lua.create_table()?;
+                        relative.set("x", packet.relative.x)?;
+                        relative.set("y", packet.relative.y)?;
+                        relative.set("z", packet.relative.z)?;
+                        relative.set("y_rot", packet.relative.y_rot)?;
+                        relative.set("x_rot", packet.relative.x_rot)?;
+
+                        let table = state.lua.create_table()?;
+                        table.set("id", packet.id.0)?;
+                        table.set("position", Vec3::from(packet.change.pos))?;
+                        table.set("velocity", Vec3::from(packet.change.delta))?;
+                        table.set("direction", Direction::from(&packet.change.look_direction))?;
+                        table.set("relative", relative)?;
+                        table.set("on_ground", packet.on_ground)?;
+                        Ok(table)
+                    })
+                    .await
+                }
+                _ => Ok(()),
             }
-            _ => Ok(()),
-        },
+        }
         Event::Login => {
-            #[cfg(feature = "matrix")]
-            matrix_init(&client, state.clone());
-
+            reconnect::finish(&client, &state).await?;
             call_listeners(&state, "login", || Ok(())).await
         }
         Event::Init => {
             debug!("received init event");
 
+            let globals = state.lua.globals();
+            lua_init(client, &state, &globals).await
+        }
+        event => {
+            debug!("ignoring unhandled event: {event:?}");
+            Ok(())
+        }
+    }
+}
+
+pub async fn handle_swarm_event(swarm: Swarm, event: SwarmEvent, state: State) -> Result<()> {
+    match event {
+        SwarmEvent::Init => {
+            debug!("received swarm init event");
+
+            let watcher_state = state.clone();
+            tokio::spawn(async move {
+                if let Err(error) = watcher::watch(watcher_state).await {
+                    error!("failed to watch scripts: {error:?}");
+                }
+            });
+
+            let globals = state.lua.globals();
+
             #[cfg(feature = "replay")]
             {
-                let ecs = client.ecs.clone();
+                let ecs = swarm.ecs.clone();
                 ctrlc::set_handler(move || {
                     ecs.write()
                         .remove_resource::<Recorder>()
                         .map(Recorder::finish);
                     exit(0);
                 })?;
-            };
 
-            let globals = state.lua.globals();
-            lua_init(client, &state, &globals).await?;
+                let ecs = swarm.ecs.clone();
+                globals.set(
+                    "finish_replay_recording",
+                    state.lua.create_function_mut(move |_, (): ()| {
+                        ecs.write()
+                            .remove_resource::<Recorder>()
+                            .context("recording not active")
+                            .map_err(Error::external)?
+                            .finish()
+                            .map_err(Error::external)
+                    })?,
+                )?;
+            };
 
             let Some(address): Option<SocketAddr> = globals
                 .get::<String>("HttpAddress")
@@ -244,6 +586,9 @@ pub async fn handle_event(client: Client, event: Event, state: State) -> Result<
                 error!("failed to listen on {address}: {error:?}");
             })?;
             debug!("http server listening on {address}");
+            if !permissions::is_configured(&state.lua) {
+                warn!("http server is unauthenticated, configure Permissions to restrict it");
+            }
 
             loop {
                 let (stream, peer) = match listener.accept().await {
@@ -258,7 +603,7 @@ pub async fn handle_event(client: Client, event: Event, state: State) -> Result<
                 let conn_state = state.clone();
                 let service = service_fn(move |request| {
                     let request_state = conn_state.clone();
-                    async move { serve(request, request_state).await }
+                    async move { serve(request, request_state, peer).await }
                 });
 
                 tokio::spawn(async move {
@@ -271,38 +616,41 @@ pub async fn handle_event(client: Client, event: Event, state: State) -> Result<
                 });
             }
         }
-        _ => todo!(),
+        SwarmEvent::Login => {
+            debug!("{} bots in swarm logged in", swarm.client_entities().len());
+
+            #[cfg(feature = "matrix")]
+            matrix_init(state);
+
+            Ok(())
+        }
+        // handled per bot by `handle_event`
+        SwarmEvent::Chat(_) | SwarmEvent::Disconnect(..) => Ok(()),
+        event => {
+            debug!("ignoring unhandled swarm event: {event:?}");
+            Ok(())
+        }
     }
 }
 
 async fn lua_init(client: Client, state: &State, globals: &Table) -> Result<()> {
-    #[cfg(feature = "replay")]
-    {
-        let ecs = client.ecs.clone();
-        globals.set(
-            "finish_replay_recording",
-            state.lua.create_function_mut(move |_, (): ()| {
-                ecs.write()
-                    .remove_resource::<Recorder>()
-                    .context("recording not active")
-                    .map_err(Error::external)?
-                    .finish()
-                    .map_err(Error::external)
-            })?,
-        )?;
+    if client.resource::<State>().name == state.name {
+        globals.set("client", client::Client(Some(client.clone())))?;
     }
-    globals.set("client", client::Client(Some(client)))?;
+    globals
+        .get::<Table>("bots")?
+        .get::<Table>(state.name.as_str())?
+        .set("client", client::Client(Some(client)))?;
     call_listeners(state, "init", || Ok(())).await
 }
 
 #[cfg(feature = "matrix")]
-fn matrix_init(client: &Client, state: State) {
+fn matrix_init(state: State) {
     let globals = state.lua.globals();
     if let Ok(options) = globals.get::<Table>("MatrixOptions") {
-        let name = client.username();
         tokio::spawn(async move {
             loop {
-                let name = name.clone();
+                let name = state.name.clone();
                 if let Err(error) = matrix::login(&state, &options, &globals, name).await {
                     error!("failed to log into matrix: {error:?}");
                 }
@@ -312,21 +660,238 @@ fn matrix_init(client: &Client, state: State) {
     }
 }
 
-pub async fn call_listeners<T, F>(state: &State, event_type: &'static str, getter: F) -> Result<()>
+async fn call_packet_listeners(state: &State, packet: &ClientboundGamePacket) -> Result<()> {
+    if !state.event_listeners.has_packet_listeners(packet.id()) {
+        return Ok(());
+    }
+
+    let event_type = format!("packet:{}", packet.name());
+    let Some(listeners) = take_listeners(&state.event_listeners, &event_type).await else {
+        return Ok(());
+    };
+
+    let table = packet::to_table(&state.lua, packet)?;
+    dispatch(
+        &state.lua,
+        &state.event_listeners,
+        &event_type,
+        listeners,
+        table.into_lua_multi(&state.lua)?,
+    );
+    Ok(())
+}
+
+pub async fn call_listeners<T, F>(state: &State, event_type: &str, getter: F) -> Result<()>
 where
     T: Clone + IntoLuaMulti + Send + 'static,
     F: FnOnce() -> Result<T>,
 {
-    if let Some(listeners) = state.event_listeners.read().await.get(event_type).cloned() {
-        let data = getter()?;
-        for (id, callback) in listeners {
-            let data = data.clone();
-            tokio::spawn(async move {
-                if let Err(error) = callback.call_async::<()>(data).await {
-                    error!("failed to call lua event listener {id} for {event_type}: {error}");
-                }
-            });
+    if let Some((listeners, data)) = prepare_event(state, event_type, getter).await? {
+        dispatch(
+            &state.lua,
+            &state.event_listeners,
+            event_type,
+            listeners,
+            data,
+        );
+    }
+    Ok(())
+}
+
+async fn prepare_event<T, F>(
+    state: &State,
+    event_type: &str,
+    getter: F,
+) -> Result<Option<(Arc<Vec<Listener>>, MultiValue)>>
+where
+    T: Clone + IntoLuaMulti + Send + 'static,
+    F: FnOnce() -> Result<T>,
+{
+    let listeners = take_listeners(&state.event_listeners, event_type).await;
+    let is_streamed = state.event_stream.has_subscribers();
+    if listeners.is_none() && !is_streamed && state.journal.is_none() {
+        return Ok(None);
+    }
+
+    let data = getter()?.into_lua_multi(&state.lua)?;
+    if is_streamed || state.journal.is_some() {
+        let json = data_to_json(&data);
+        if is_streamed {
+            state.event_stream.send(&state.name, event_type, &json);
+        }
+        if let Some(journal) = &state.journal {
+            journal.write(&state.name, event_type, &json);
+        }
+    }
+    Ok(listeners.map(|listeners| (listeners, data)))
+}
+
+/// Calls a custom event from outside the game on `bot`, or on every bot if it's `None`.
+pub async fn emit(state: &State, bot: Option<&str>, event_type: &str, data: &str) -> Result<()> {
+    if BUILTIN_EVENTS.contains(&event_type) || event_type.contains(':') {
+        bail!("{event_type} is a built-in event");
+    }
+    if let Some(bot) = bot
+        && !state.bots.iter().any(|(name, _)| name == bot)
+    {
+        bail!("unknown bot {bot}");
+    }
+
+    let data = if data.trim().is_empty() {
+        Value::Nil
+    } else {
+        state
+            .lua
+            .to_value(&serde_json::from_str::<JsonValue>(data)?)?
+    };
+    for (name, event_listeners) in state.bots.iter() {
+        if bot.is_none_or(|bot| bot == name) {
+            let state = State {
+                name: name.clone(),
+                event_listeners: event_listeners.clone(),
+                ..state.clone()
+            };
+            call_listeners(&state, event_type, || Ok(data.clone())).await?;
         }
     }
     Ok(())
 }
+
+/// Calls `listeners` with `data` in the background.
+pub fn dispatch(
+    lua: &Lua,
+    event_listeners: &ListenerMap,
+    event_type: &str,
+    listeners: Arc<Vec<Listener>>,
+    data: MultiValue,
+) {
+    tokio::spawn(call_all(
+        lua.clone(),
+        event_listeners.clone(),
+        event_type.to_owned(),
+        listeners,
+        data,
+        false,
+    ));
+}
+
+/// Calls `listeners` with `data`, waiting for each of them to finish if `wait` is set.
+pub async fn call_all(
+    lua: Lua,
+    event_listeners: ListenerMap,
+    event_type: String,
+    listeners: Arc<Vec<Listener>>,
+    data: MultiValue,
+    wait: bool,
+) {
+    if is_sequential(&lua, &event_type) {
+        call_in_order(&lua, &event_listeners, &event_type, &listeners, data).await;
+        return;
+    }
+
+    let hot = HOT_EVENTS.contains(&event_type.as_str());
+    for listener in listeners.iter().filter(|listener| !listener.is_disabled()) {
+        let (lua, event_listeners, event_type, listener, data) = (
+            lua.clone(),
+            event_listeners.clone(),
+            event_type.clone(),
+            listener.clone(),
+            data.clone(),
+        );
+        let mut call = Box::pin(async move {
+            if let Err(error) = listener.call::<()>(data).await {
+                listener_failed(&lua, &event_listeners, &event_type, &listener, &error).await;
+            }
+        });
+        if wait {
+            call.await;
+        } else if !hot || poll!(&mut call).is_pending() {
+            tokio::spawn(call);
+        }
+    }
+}
+
+fn is_sequential(lua: &Lua, event_type: &str) -> bool {
+    lua.globals()
+        .get::<Vec<String>>("SequentialEvents")
+        .is_ok_and(|events| events.iter().any(|event| event == event_type))
+}
+
+async fn call_in_order(
+    lua: &Lua,
+    event_listeners: &ListenerMap,
+    event_type: &str,
+    listeners: &[Listener],
+    mut data: MultiValue,
+) {
+    for listener in listeners.iter().filter(|listener| !listener.is_disabled()) {
+        match listener.call::<MultiValue>(data.clone()).await {
+            Ok(result) => match result.front() {
+                Some(Value::Boolean(false)) => break,
+                None | Some(Value::Nil | Value::Boolean(true)) => (),
+                Some(_) => data = result,
+            },
+            Err(error) => listener_failed(lua, event_listeners, event_type, listener, &error).await,
+        }
+    }
+}
+
+struct Ticking(Arc<AtomicBool>);
+
+impl Drop for Ticking {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Logs a listener's error, and disables the listener if it keeps failing.
+pub async fn listener_failed(
+    lua: &Lua,
+    event_listeners: &ListenerMap,
+    event_type: &str,
+    listener: &Listener,
+    error: &mlua::Error,
+) {
+    error!(
+        "failed to call lua event listener {} for {event_type}: {error}",
+        listener.id
+    );
+
+    let Some(max_failures) = lua
+        .globals()
+        .get::<Table>("ListenerOptions")
+        .and_then(|options| options.get::<Option<u32>>("max_failures"))
+        .ok()
+        .flatten()
+    else {
+        return;
+    };
+    let failures = {
+        let mut stats = listener.stats.lock();
+        if stats.consecutive_failures < max_failures || stats.disabled {
+            return;
+        }
+        stats.disabled = true;
+        stats.consecutive_failures
+    };
+
+    warn!(
+        "disabling lua event listener {} for {event_type} after {failures} consecutive failures",
+        listener.id
+    );
+    let Some(listeners) = take_listeners(event_listeners, "listener_disabled").await else {
+        return;
+    };
+    let data = || {
+        let table = lua.create_table()?;
+        table.set("event_type", event_type)?;
+        table.set("id", listener.id.as_str())?;
+        table.set("failures", failures)?;
+        table.set("error", error.to_string())?;
+        table.into_lua_multi(lua)
+    };
+    match data() {
+        Ok(data) => dispatch(lua, event_listeners, "listener_disabled", listeners, data),
+        Err(error) => error!("failed to create listener_disabled event: {error}"),
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    fs,
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use hyper::{
+    header::AUTHORIZATION,
+    http::{request::Parts, uri::PathAndQuery},
+};
+use mlua::{Lua, Table};
+use parking_lot::Mutex;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+use crate::permissions;
+
+const MAX_CLOCK_SKEW: u64 = 300;
+
+static SEEN_SIGNATURES: LazyLock<Mutex<HashMap<Vec<u8>, u64>>> = LazyLock::new(Mutex::default);
+
+/// Authenticates a request from its headers alone, before its body is read.
+pub fn authenticate(lua: &Lua, parts: &Parts) -> Result<Verifier, &'static str> {
+    if !permissions::is_configured(lua) {
+        return Ok(Verifier::Done(None));
+    }
+    let principals = lua
+        .globals()
+        .get::<Table>("Permissions")
+        .and_then(|permissions| permissions.get::<Table>("http"))
+        .map_err(|_| "no HTTP principals configured")?;
+
+    let authorization = parts
+        .headers
+        .get(AUTHORIZATION)
+        .ok_or("missing authorization header")?
+        .to_str()
+        .map_err(|_| "malformed authorization header")?;
+
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        principals
+            .pairs::<String, Table>()
+            .filter_map(Result::ok)
+            .find(|(_, principal)| {
+                credential(principal, "token")
+                    .is_some_and(|expected| bool::from(expected.as_bytes().ct_eq(token.as_bytes())))
+            })
+            .map(|(name, _)| Verifier::Done(Some(name)))
+            .ok_or("invalid bearer token")
+    } else if let Some(signature) = authorization.strip_prefix("Signature ") {
+        let (name, signature) = signature.split_once(':').ok_or("malformed signature")?;
+        let signature = hex::decode(signature).map_err(|_| "malformed signature")?;
+        let timestamp = parts
+            .headers
+            .get("x-timestamp")
+            .and_then(|value| value.to_str().ok())
+            .ok_or("missing timestamp")?;
+        if timestamp
+            .parse::<u64>()
+            .map_err(|_| "malformed timestamp")?
+            .abs_diff(now())
+            > MAX_CLOCK_SKEW
+        {
+            return Err("stale timestamp");
+        }
+
+        let secret = principals
+            .get::<Table>(name)
+            .ok()
+            .and_then(|principal| credential(&principal, "secret"))
+            .ok_or("unknown principal")?;
+        Ok(Verifier::Signature {
+            name: name.to_owned(),
+            secret,
+            signature,
+            timestamp: timestamp.to_owned(),
+        })
+    } else {
+        Err("unsupported authorization scheme")
+    }
+}
+
+pub enum Verifier {
+    Done(Option<String>),
+    Signature {
+        name: String,
+        secret: String,
+        signature: Vec<u8>,
+        timestamp: String,
+    },
+}
+
+impl Verifier {
+    /// Returns the authenticated principal, if any, after checking the signature against `body`.
+    pub fn verify(self, parts: &Parts, body: &[u8]) -> Result<Option<String>, &'static str> {
+        let (name, secret, signature, timestamp) = match self {
+            Self::Done(principal) => return Ok(principal),
+            Self::Signature {
+                name,
+                secret,
+                signature,
+                timestamp,
+            } => (name, secret, signature, timestamp),
+        };
+
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|_| "invalid secret")?;
+        mac.update(
+            format!(
+                "{timestamp}\n{}\n{}\n",
+                parts.method,
+                parts.uri.path_and_query().map_or("/", PathAndQuery::as_str)
+            )
+            .as_bytes(),
+        );
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| "invalid signature")?;
+
+        let now = now();
+        let mut seen = SEEN_SIGNATURES.lock();
+        seen.retain(|_, timestamp| timestamp.abs_diff(now) <= MAX_CLOCK_SKEW);
+        if seen
+            .insert(signature, timestamp.parse().unwrap_or_default())
+            .is_some()
+        {
+            return Err("reused signature");
+        }
+        Ok(Some(name))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn credential(principal: &Table, key: &str) -> Option<String> {
+    principal.get::<String>(key).ok().or_else(|| {
+        principal
+            .get::<String>(format!("{key}_file"))
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| contents.trim().to_owned())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        lua.load(
+            r#"
+            Permissions = {
+                http = {
+                    alice = { token = "alice-token", secret = "alice-secret" },
+                    bob = { token = "bob-token" },
+                },
+            }
+            "#,
+        )
+        .exec()
+        .unwrap();
+        lua
+    }
+
+    fn request(uri: &str, authorization: &str, timestamp: Option<u64>) -> Parts {
+        let mut request = Request::post(uri).header(AUTHORIZATION, authorization);
+        if let Some(timestamp) = timestamp {
+            request = request.header("x-timestamp", timestamp.to_string());
+        }
+        request.body(()).unwrap().into_parts().0
+    }
+
+    fn sign(secret: &str, timestamp: u64, uri: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{timestamp}\nPOST\n{uri}\n").as_bytes());
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn signed(uri: &str, signed_uri: &str, body: &[u8], timestamp: u64) -> Parts {
+        let signature = sign("alice-secret", timestamp, signed_uri, body);
+        request(
+            uri,
+            &format!("Signature alice:{signature}"),
+            Some(timestamp),
+        )
+    }
+
+    fn check(lua: &Lua, parts: &Parts, body: &[u8]) -> Result<Option<String>, &'static str> {
+        authenticate(lua, parts)?.verify(parts, body)
+    }
+
+    #[test]
+    fn matches_bearer_tokens() {
+        let lua = lua();
+        for (token, expected) in [("alice-token", "alice"), ("bob-token", "bob")] {
+            let parts = request("/exec", &format!("Bearer {token}"), None);
+            assert_eq!(check(&lua, &parts, b""), Ok(Some(String::from(expected))));
+        }
+        let parts = request("/exec", "Bearer alice-secret", None);
+        assert_eq!(check(&lua, &parts, b"").err(), Some("invalid bearer token"));
+        let parts = request("/exec", "Basic abc", None);
+        assert!(authenticate(&lua, &parts).is_err());
+    }
+
+    #[test]
+    fn allows_everything_without_permissions() {
+        let parts = request("/exec", "Bearer anything", None);
+        assert_eq!(check(&Lua::new(), &parts, b""), Ok(None));
+    }
+
+    #[test]
+    fn accepts_good_signatures() {
+        let body = b"print('good')";
+        let parts = signed("/exec?bot=A", "/exec?bot=A", body, now());
+        assert_eq!(check(&lua(), &parts, body), Ok(Some(String::from("alice"))));
+    }
+
+    #[test]
+    fn rejects_tampered_bodies() {
+        let parts = signed("/exec", "/exec", b"print('tampered')", now());
+        assert_eq!(
+            check(&lua(), &parts, b"os.exit()").err(),
+            Some("invalid signature")
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_queries() {
+        let body = b"print('query')";
+        let parts = signed("/exec?bot=B", "/exec?bot=A", body, now());
+        assert_eq!(check(&lua(), &parts, body).err(), Some("invalid signature"));
+    }
+
+    #[test]
+    fn rejects_reused_signatures() {
+        let lua = lua();
+        let body = b"print('reused')";
+        let parts = signed("/exec", "/exec", body, now());
+        assert!(check(&lua, &parts, body).is_ok());
+        assert_eq!(check(&lua, &parts, body).err(), Some("reused signature"));
+    }
+
+    #[test]
+    fn rejects_stale_timestamps() {
+        let lua = lua();
+        let body = b"print('stale')";
+        for timestamp in [now() - MAX_CLOCK_SKEW - 10, now() + MAX_CLOCK_SKEW + 10] {
+            let parts = signed("/exec", "/exec", body, timestamp);
+            assert_eq!(authenticate(&lua, &parts).err(), Some("stale timestamp"));
+        }
+        let parts = signed("/exec", "/exec", body, now() - MAX_CLOCK_SKEW + 10);
+        assert!(check(&lua, &parts, body).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_principals() {
+        let timestamp = now();
+        let signature = sign("bob-secret", timestamp, "/exec", b"");
+        let parts = request(
+            "/exec",
+            &format!("Signature bob:{signature}"),
+            Some(timestamp),
+        );
+        assert_eq!(
+            authenticate(&lua(), &parts).err(),
+            Some("unknown principal")
+        );
+    }
+}
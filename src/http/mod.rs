@@ -0,0 +1,131 @@
+mod auth;
+mod state;
+pub mod stream;
+
+use std::net::SocketAddr;
+
+use http_body_util::{BodyExt, Empty, Full, LengthLimitError, Limited, combinators::BoxBody};
+use hyper::{
+    Error, Method, Request, Response, StatusCode,
+    body::{Bytes, Incoming},
+};
+use log::warn;
+
+use crate::{
+    State,
+    events::emit,
+    lua::{Frontend, Sender, eval, exec, reload},
+    permissions,
+};
+
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+pub async fn serve(
+    request: Request<Incoming>,
+    state: State,
+    peer: SocketAddr,
+) -> Result<Response<BoxBody<Bytes, Error>>, Error> {
+    let (parts, body) = request.into_parts();
+    let command = match (&parts.method, parts.uri.path()) {
+        (&Method::POST, "/reload" | "/eval" | "/exec") | (&Method::GET, "/ping") => {
+            &parts.uri.path()[1..]
+        }
+        (&Method::GET, "/events") => "events",
+        (&Method::POST, path) if path.len() > "/events/".len() && path.starts_with("/events/") => {
+            "emit"
+        }
+        (&Method::GET, path) if path == "/bots" || path.starts_with("/bots/") => "state",
+        _ => return Ok(status_code_response(StatusCode::NOT_FOUND, empty())),
+    };
+    let verifier = match auth::authenticate(&state.lua, &parts) {
+        Ok(verifier) => verifier,
+        Err(reason) => {
+            warn!("rejected HTTP request to /{command} from {peer}: {reason}");
+            return Ok(status_code_response(StatusCode::UNAUTHORIZED, empty()));
+        }
+    };
+    let body = match Limited::new(body, MAX_BODY_SIZE).collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(error) if error.is::<LengthLimitError>() => {
+            return Ok(status_code_response(StatusCode::PAYLOAD_TOO_LARGE, empty()));
+        }
+        Err(error) => {
+            warn!("failed to read HTTP request body from {peer}: {error}");
+            return Ok(status_code_response(StatusCode::BAD_REQUEST, empty()));
+        }
+    };
+    let principal = match verifier.verify(&parts, &body) {
+        Ok(principal) => principal,
+        Err(reason) => {
+            warn!("rejected HTTP request to /{command} from {peer}: {reason}");
+            return Ok(status_code_response(StatusCode::UNAUTHORIZED, empty()));
+        }
+    };
+    let sender = Sender {
+        name: principal.unwrap_or_else(|| peer.to_string()),
+        uuid: None,
+        frontend: Frontend::Http,
+    };
+    if !permissions::is_allowed(&state.lua, &sender, command) {
+        warn!(
+            "rejected HTTP request to /{command} from {} ({peer}): not allowed",
+            sender.name
+        );
+        return Ok(status_code_response(StatusCode::FORBIDDEN, empty()));
+    }
+
+    let sender = Some(sender);
+    Ok(match command {
+        "reload" => Response::new(
+            reload(&state.lua, sender).map_or_else(|error| full(error.to_string()), |()| empty()),
+        ),
+        "eval" => Response::new(full(
+            eval::<String>(&state.lua, &String::from_utf8_lossy(&body), sender)
+                .await
+                .unwrap_or_else(|error| error.to_string()),
+        )),
+        "exec" => Response::new(
+            exec(&state.lua, &String::from_utf8_lossy(&body), sender)
+                .await
+                .map_or_else(|error| full(error.to_string()), |()| empty()),
+        ),
+        "events" => state.event_stream.subscribe(parts.uri.query()),
+        "emit" => emit(
+            &state,
+            parts
+                .uri
+                .query()
+                .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("bot="))),
+            &parts.uri.path()["/events/".len()..],
+            &String::from_utf8_lossy(&body),
+        )
+        .await
+        .map_or_else(
+            |error| status_code_response(StatusCode::BAD_REQUEST, full(error.to_string())),
+            |()| Response::new(empty()),
+        ),
+        "state" => state::respond(&state.lua, parts.uri.path(), parts.uri.query()),
+        _ => Response::new(full("pong!")),
+    })
+}
+
+fn status_code_response(
+    status_code: StatusCode,
+    bytes: BoxBody<Bytes, Error>,
+) -> Response<BoxBody<Bytes, Error>> {
+    let mut response = Response::new(bytes);
+    *response.status_mut() = status_code;
+    response
+}
+
+fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, Error> {
+    Full::new(chunk.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+fn empty() -> BoxBody<Bytes, Error> {
+    Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed()
+}
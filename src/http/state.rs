@@ -0,0 +1,240 @@
+use azalea::{
+    Client as AzaleaClient,
+    core::entity_id::MinecraftEntityId,
+    entity::{
+        EntityKindComponent, EntityUuid, LookDirection, Pose, Position as AzaleaPosition,
+        inventory::Inventory,
+        metadata::{CustomName, Health, Owneruuid},
+    },
+    inventory::{
+        ItemStack,
+        components::{self, Damage, MaxDamage},
+    },
+    local_player::{Experience, Hunger, TabList},
+    pathfinder::{ExecutingPath, Pathfinder, PathfinderClientExt},
+    player::GameProfileComponent,
+    world::WorldName,
+};
+use http_body_util::combinators::BoxBody;
+use hyper::{Response, StatusCode, body::Bytes, header::CONTENT_TYPE};
+use mlua::{Lua, Table, UserDataRef};
+use serde_json::{Value, json};
+
+use super::{empty, full, status_code_response};
+use crate::{
+    get_entities,
+    lua::{client::Client, direction::Direction, vec3::Vec3},
+};
+
+const DEFAULT_ENTITY_RADIUS: f64 = 64.0;
+
+/// Serves `GET /bots` and `GET /bots/<name>[/<resource>]`.
+pub fn respond(
+    lua: &Lua,
+    path: &str,
+    query: Option<&str>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut segments = path.trim_matches('/').split('/').skip(1);
+    let (name, resource) = (segments.next(), segments.next());
+    if segments.next().is_some() {
+        return status_code_response(StatusCode::NOT_FOUND, empty());
+    }
+
+    let Some(name) = name else {
+        return json_response(&Value::from(bot_names(lua)));
+    };
+    let Some(client) = bot_client(lua, name) else {
+        return status_code_response(StatusCode::NOT_FOUND, empty());
+    };
+
+    let value = match resource {
+        None => status(&client),
+        Some("tab_list") => tab_list(&client),
+        Some("inventory") => inventory(&client),
+        Some("menu") => menu(&client),
+        Some("pathfinder") => pathfinder(&client),
+        Some("entities") => entities(
+            &client,
+            query
+                .and_then(|query| {
+                    query
+                        .split('&')
+                        .find_map(|pair| pair.strip_prefix("radius="))
+                })
+                .and_then(|radius| radius.parse().ok())
+                .unwrap_or(DEFAULT_ENTITY_RADIUS),
+        ),
+        Some(_) => return status_code_response(StatusCode::NOT_FOUND, empty()),
+    };
+    // components are missing while the bot is disconnected or still joining
+    value.map_or_else(
+        || status_code_response(StatusCode::SERVICE_UNAVAILABLE, empty()),
+        |value| json_response(&value),
+    )
+}
+
+fn json_response(value: &Value) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut response = Response::new(full(value.to_string()));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        "application/json"
+            .parse()
+            .expect("header value should be valid"),
+    );
+    response
+}
+
+fn bot_names(lua: &Lua) -> Vec<String> {
+    lua.globals()
+        .get::<Table>("bots")
+        .map(|bots| {
+            bots.pairs::<String, Table>()
+                .filter_map(|pair| pair.ok().map(|(name, _)| name))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn bot_client(lua: &Lua, name: &str) -> Option<AzaleaClient> {
+    lua.globals()
+        .get::<Table>("bots")
+        .ok()?
+        .get::<Table>(name)
+        .ok()?
+        .get::<UserDataRef<Client>>("client")
+        .ok()?
+        .0
+        .clone()
+}
+
+fn status(client: &AzaleaClient) -> Option<Value> {
+    let position = **client.get_component::<AzaleaPosition>()?;
+    let direction = Direction::from(&*client.get_component::<LookDirection>()?);
+    let hunger = client.get_component::<Hunger>()?.clone();
+    let experience = client.get_component::<Experience>()?.clone();
+    let (held_slot, held_item) = {
+        let inventory = client.get_component::<Inventory>()?;
+        (
+            inventory.selected_hotbar_slot,
+            item_stack(inventory.held_item()),
+        )
+    };
+    Some(json!({
+        "username": client.get_component::<GameProfileComponent>()?.name,
+        "uuid": client.get_component::<EntityUuid>()?.to_string(),
+        "position": { "x": position.x, "y": position.y, "z": position.z },
+        "direction": { "y": direction.y, "x": direction.x },
+        "health": **client.get_component::<Health>()?,
+        "hunger": { "food": hunger.food, "saturation": hunger.saturation },
+        "experience": {
+            "progress": experience.progress,
+            "total": experience.total,
+            "level": experience.level,
+        },
+        "dimension": client.get_component::<WorldName>()?.to_string(),
+        "held_slot": held_slot,
+        "held_item": held_item,
+    }))
+}
+
+fn tab_list(client: &AzaleaClient) -> Option<Value> {
+    Some(
+        client
+            .get_component::<TabList>()?
+            .values()
+            .map(|info| {
+                json!({
+                    "display_name": info.display_name.as_ref().map(ToString::to_string),
+                    "gamemode": info.gamemode.to_id(),
+                    "latency": info.latency,
+                    "name": info.profile.name,
+                    "uuid": info.uuid.to_string(),
+                })
+            })
+            .collect(),
+    )
+}
+
+fn inventory(client: &AzaleaClient) -> Option<Value> {
+    let inventory = client.get_component::<Inventory>()?;
+    Some(json!({
+        "held_slot": inventory.selected_hotbar_slot,
+        "slots": inventory.inventory_menu.slots().iter().map(item_stack).collect::<Vec<_>>(),
+    }))
+}
+
+fn menu(client: &AzaleaClient) -> Option<Value> {
+    let inventory = client.get_component::<Inventory>()?;
+    Some(json!({
+        "id": inventory.id,
+        "title": inventory.container_menu_title.as_ref().map(ToString::to_string),
+        "slots": inventory.menu().slots().iter().map(item_stack).collect::<Vec<_>>(),
+    }))
+}
+
+fn pathfinder(client: &AzaleaClient) -> Option<Value> {
+    let is_calculating = client.get_component::<Pathfinder>()?.is_calculating;
+    let mut value = json!({
+        "is_calculating": is_calculating,
+        "is_executing": false,
+        "goal_reached": client.is_goto_target_reached(),
+    });
+    if let Some(path) = client.get_component::<ExecutingPath>() {
+        value["is_executing"] = true.into();
+        value["last_reached_node"] = json!({
+            "x": path.last_reached_node.x,
+            "y": path.last_reached_node.y,
+            "z": path.last_reached_node.z,
+        });
+        value["ticks_since_last_node_reached"] = path.ticks_since_last_node_reached.into();
+        value["is_path_partial"] = path.is_path_partial.into();
+    }
+    Some(value)
+}
+
+fn entities(client: &AzaleaClient, radius: f64) -> Option<Value> {
+    let own_uuid = client.get_component::<EntityUuid>()?.to_string();
+    let origin = **client.get_component::<AzaleaPosition>()?;
+    let distance_to =
+        |position: &Vec3| azalea::Vec3::new(position.x, position.y, position.z).distance_to(origin);
+
+    let mut nearby = get_entities!(client)
+        .into_iter()
+        .filter(|entity| entity.3 != own_uuid && distance_to(&entity.0) <= radius)
+        .collect::<Vec<_>>();
+    nearby.sort_by(|a, b| distance_to(&a.0).total_cmp(&distance_to(&b.0)));
+    Some(
+        nearby
+            .into_iter()
+            .map(
+                |(position, custom_name, kind, uuid, direction, id, owner_uuid, pose)| {
+                    json!({
+                        "distance": distance_to(&position),
+                        "position": { "x": position.x, "y": position.y, "z": position.z },
+                        "custom_name": custom_name,
+                        "kind": kind,
+                        "uuid": uuid,
+                        "direction": { "y": direction.y, "x": direction.x },
+                        "id": id,
+                        "owner_uuid": owner_uuid.and_then(|v| *v).map(|v| v.to_string()),
+                        "pose": pose,
+                    })
+                },
+            )
+            .collect(),
+    )
+}
+
+fn item_stack(item_stack: &ItemStack) -> Value {
+    item_stack.as_present().map_or(Value::Null, |data| {
+        json!({
+            "kind": data.kind.to_string(),
+            "count": data.count,
+            "custom_name": data
+                .get_component::<components::CustomName>()
+                .map(|c| c.name.to_string()),
+            "damage": data.get_component::<Damage>().map(|d| d.amount),
+            "max_damage": data.get_component::<MaxDamage>().map(|d| d.amount),
+        })
+    })
+}
@@ -0,0 +1,150 @@
+use std::{collections::HashSet, sync::Arc};
+
+use futures::stream;
+use http_body_util::{BodyExt, StreamBody, combinators::BoxBody};
+use hyper::{
+    Response,
+    body::{Bytes, Frame},
+    header::{CACHE_CONTROL, CONTENT_TYPE},
+};
+use log::debug;
+use mlua::{MultiValue, Table, Value};
+use serde_json::{Map, Number, Value as JsonValue, json};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::lua::container::item_stack::ItemStack;
+
+const CAPACITY: usize = 256;
+
+/// Broadcasts every dispatched event as JSON to `GET /events` subscribers.
+#[derive(Clone)]
+pub struct EventStream(broadcast::Sender<Arc<Event>>);
+
+struct Event {
+    bot: String,
+    kind: String,
+    json: String,
+}
+
+impl Default for EventStream {
+    fn default() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+}
+
+impl EventStream {
+    pub fn has_subscribers(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+
+    pub fn send(&self, bot: &str, kind: &str, data: &JsonValue) {
+        let _ = self.0.send(Arc::new(Event {
+            bot: bot.to_owned(),
+            kind: kind.to_owned(),
+            json: json!({ "bot": bot, "type": kind, "data": data }).to_string(),
+        }));
+    }
+
+    /// Responds with a `text/event-stream` of events, filtered by the `types` and `bots` query.
+    pub fn subscribe(&self, query: Option<&str>) -> Response<BoxBody<Bytes, hyper::Error>> {
+        let filter = |name| {
+            query.and_then(|query| {
+                query.split('&').find_map(|pair| {
+                    pair.strip_prefix(name)
+                        .and_then(|value| value.strip_prefix('='))
+                        .map(|value| value.split(',').map(str::to_owned).collect::<HashSet<_>>())
+                })
+            })
+        };
+        let (types, bots) = (filter("types"), filter("bots"));
+
+        let events = stream::unfold(self.0.subscribe(), move |mut receiver| {
+            let (types, bots) = (types.clone(), bots.clone());
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event)
+                            if types
+                                .as_ref()
+                                .is_none_or(|types| types.contains(&event.kind))
+                                && bots.as_ref().is_none_or(|bots| bots.contains(&event.bot)) =>
+                        {
+                            let frame = Frame::data(Bytes::from(format!(
+                                "event: {}\ndata: {}\n\n",
+                                event.kind, event.json
+                            )));
+                            return Some((Ok(frame), receiver));
+                        }
+                        Ok(_) => (),
+                        Err(RecvError::Lagged(skipped)) => {
+                            debug!("event stream subscriber lagged behind by {skipped} events");
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+
+        let mut response = Response::new(StreamBody::new(events).boxed());
+        let headers = response.headers_mut();
+        headers.insert(
+            CONTENT_TYPE,
+            "text/event-stream"
+                .parse()
+                .expect("header value should be valid"),
+        );
+        headers.insert(
+            CACHE_CONTROL,
+            "no-cache".parse().expect("header value should be valid"),
+        );
+        response
+    }
+}
+
+/// Converts event data to JSON, as null for no values, the value itself for one, or an array.
+pub fn data_to_json(values: &MultiValue) -> JsonValue {
+    match values.len() {
+        0 => JsonValue::Null,
+        1 => to_json(&values[0]),
+        _ => values.iter().map(to_json).collect(),
+    }
+}
+
+fn to_json(value: &Value) -> JsonValue {
+    match value {
+        Value::Nil => JsonValue::Null,
+        Value::Boolean(boolean) => JsonValue::Bool(*boolean),
+        Value::Integer(integer) => JsonValue::from(*integer),
+        Value::Number(number) => {
+            Number::from_f64(*number).map_or(JsonValue::Null, JsonValue::Number)
+        }
+        Value::String(string) => JsonValue::String(string.to_string_lossy()),
+        Value::Table(table) => table_to_json(table),
+        Value::UserData(userdata) if let Ok(item_stack) = userdata.borrow::<ItemStack>() => json!({
+            "kind": item_stack.0.kind().to_string(),
+            "count": item_stack.0.count(),
+            "components": serde_json::to_value(item_stack.0.component_patch()).unwrap_or_default(),
+        }),
+        other => other.to_string().map_or(JsonValue::Null, JsonValue::String),
+    }
+}
+
+fn table_to_json(table: &Table) -> JsonValue {
+    let length = table.raw_len();
+    // only a pure 1..n sequence becomes an array, anything else would lose keys
+    if length > 0 && table.pairs::<Value, Value>().count() == length {
+        return table
+            .sequence_values::<Value>()
+            .filter_map(Result::ok)
+            .map(|value| to_json(&value))
+            .collect();
+    }
+
+    let mut map = Map::new();
+    for (key, value) in table.pairs::<Value, Value>().filter_map(Result::ok) {
+        if let Ok(key) = key.to_string() {
+            map.insert(key, to_json(&value));
+        }
+    }
+    JsonValue::Object(map)
+}
@@ -0,0 +1,92 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, LineWriter, Write},
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use mlua::{IntoLuaMulti, LuaSerdeExt, SerializeOptions};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_json::{Value as JsonValue, json};
+
+use crate::{State, events::call_all, lua::events::take_listeners};
+
+/// Appends every event passed to listeners to a JSON lines file, if `JournalOptions` is set.
+#[derive(Clone)]
+pub struct Journal(Arc<Mutex<LineWriter<File>>>);
+
+#[derive(Deserialize)]
+struct Entry {
+    bot: String,
+    #[serde(rename = "type")]
+    kind: String,
+    data: JsonValue,
+}
+
+impl Journal {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("failed to open event journal {path}"))?;
+        Ok(Self(Arc::new(Mutex::new(LineWriter::new(file)))))
+    }
+
+    pub fn write(&self, bot: &str, kind: &str, data: &JsonValue) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let line = json!({ "time": time, "bot": bot, "type": kind, "data": data });
+        let result = writeln!(self.0.lock(), "{line}");
+        if let Err(error) = result {
+            warn!("failed to write to event journal: {error}");
+        }
+    }
+}
+
+/// Calls the listeners of each bot with every event in the journal at `path`, one at a time.
+pub async fn replay(path: &Path, states: &[State]) -> Result<()> {
+    let file = File::open(path)
+        .with_context(|| format!("failed to open event journal {}", path.display()))?;
+    let options = SerializeOptions::new()
+        .serialize_none_to_null(false)
+        .serialize_unit_to_null(false);
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str::<Entry>(&line)
+            .with_context(|| format!("invalid journal entry on line {}", index + 1))?;
+        let Some(state) = states
+            .iter()
+            .find(|state| state.name == entry.bot)
+            .or_else(|| states.first())
+        else {
+            return Ok(());
+        };
+        let Some(listeners) = take_listeners(&state.event_listeners, &entry.kind).await else {
+            continue;
+        };
+
+        let data = state.lua.to_value_with(&entry.data, options)?;
+        call_all(
+            state.lua.as_ref().clone(),
+            state.event_listeners.clone(),
+            entry.kind,
+            listeners,
+            data.into_lua_multi(&state.lua)?,
+            true,
+        )
+        .await;
+    }
+
+    Ok(())
+}
@@ -9,14 +9,16 @@ mod world;
 use std::ops::Deref;
 
 use azalea::{Client as AzaleaClient, core::entity_id::MinecraftEntityId};
-use mlua::{Lua, Result, UserData, UserDataFields, UserDataMethods};
+use mlua::{Lua, Result, UserData, UserDataFields, UserDataMethods, Value};
 
 use super::{
     container::{Container, ContainerRef, item_stack::ItemStack},
     direction::Direction,
+    packet,
     player::Player,
     vec3::Vec3,
 };
+use crate::{State, chat::Priority, reconnect::DisconnectRequested};
 
 pub struct Client(pub Option<AzaleaClient>);
 
@@ -31,6 +33,7 @@ impl Deref for Client {
 impl UserData for Client {
     fn add_fields<F: UserDataFields<Self>>(f: &mut F) {
         f.add_field_method_get("air_supply", state::air_supply);
+        f.add_field_method_get("chat_queue", chat_queue);
         f.add_field_method_get("container", container::container);
         f.add_field_method_get("dimension", world::dimension);
         f.add_field_method_get("direction", movement::get_direction);
@@ -83,6 +86,7 @@ impl UserData for Client {
         m.add_method("get_fluid_state", world::get_fluid_state);
         m.add_method("jump", movement::jump);
         m.add_method("open_inventory", container::open_inventory);
+        m.add_method("send_packet", send_packet);
         m.add_method("set_component", state::set_component);
         m.add_method("sprint", movement::sprint);
         m.add_method("start_mining", interaction::start_mining);
@@ -93,12 +97,22 @@ impl UserData for Client {
     }
 }
 
-fn chat(_lua: &Lua, client: &Client, message: String) -> Result<()> {
-    client.chat(&message);
-    Ok(())
+fn chat(lua: &Lua, client: &Client, message: String) -> Result<bool> {
+    let chat = client.component::<State>().chat.clone();
+    Ok(chat.push(lua, message, Priority::Normal))
+}
+
+fn chat_queue(_lua: &Lua, client: &Client) -> Result<usize> {
+    let chat = client.component::<State>().chat.clone();
+    Ok(chat.len())
 }
 
 fn disconnect(_lua: &Lua, client: &Client, (): ()) -> Result<()> {
+    client
+        .ecs
+        .write()
+        .entity_mut(client.entity)
+        .insert(DisconnectRequested);
     client.disconnect();
     Ok(())
 }
@@ -107,6 +121,11 @@ fn id(_lua: &Lua, client: &Client) -> Result<i32> {
     Ok(client.component::<MinecraftEntityId>().0)
 }
 
+fn send_packet(lua: &Lua, client: &Client, (name, data): (String, Value)) -> Result<()> {
+    client.write_packet(packet::build(lua, &name, data)?);
+    Ok(())
+}
+
 fn tab_list(_lua: &Lua, client: &Client) -> Result<Vec<Player>> {
     Ok(client.tab_list().into_values().map(Player::from).collect())
 }
@@ -0,0 +1,375 @@
+use std::{any::Any, collections::HashSet, sync::Arc};
+
+use azalea::brigadier::{
+    arguments::ArgumentType,
+    builder::argument_builder::ArgumentBuilder,
+    errors::{BuiltInError, CommandSyntaxError},
+    prelude::*,
+    string_reader::StringReader,
+};
+use futures::lock::Mutex;
+use log::error;
+use mlua::{Error, Function, IntoLua, Lua, Result, Table, Value};
+
+use super::vec3::Vec3;
+use crate::commands::{CommandSource, Ctx, allowed};
+
+type Dispatcher = Arc<CommandDispatcher<Mutex<CommandSource>>>;
+
+struct LuaCommands {
+    dispatcher: Dispatcher,
+    names: HashSet<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Word,
+    String,
+    Text,
+    Integer,
+    Float,
+    Player,
+    Position,
+}
+
+struct Parameter {
+    name: String,
+    kind: Kind,
+    optional: bool,
+}
+
+enum Argument {
+    String(String),
+    Integer(i32),
+    Float(f64),
+    Position(Vec3),
+}
+
+impl IntoLua for Argument {
+    fn into_lua(self, lua: &Lua) -> Result<Value> {
+        match self {
+            Self::String(string) => string.into_lua(lua),
+            Self::Integer(integer) => integer.into_lua(lua),
+            Self::Float(float) => float.into_lua(lua),
+            Self::Position(position) => position.into_lua(lua),
+        }
+    }
+}
+
+struct PlayerArgument;
+
+impl ArgumentType for PlayerArgument {
+    fn parse(
+        &self,
+        reader: &mut StringReader,
+    ) -> std::result::Result<Arc<dyn Any>, CommandSyntaxError> {
+        let start = reader.cursor;
+        let name = reader.read_unquoted_string().to_owned();
+        if name.is_empty()
+            || name.len() > 16
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            reader.cursor = start;
+            return Err(BuiltInError::DispatcherParseException {
+                message: format!("Invalid player name '{name}'"),
+            }
+            .create_with_context(reader));
+        }
+        Ok(Arc::new(name))
+    }
+}
+
+struct PositionArgument;
+
+impl ArgumentType for PositionArgument {
+    fn parse(
+        &self,
+        reader: &mut StringReader,
+    ) -> std::result::Result<Arc<dyn Any>, CommandSyntaxError> {
+        let x = reader.read_double()?;
+        reader.expect(' ')?;
+        let y = reader.read_double()?;
+        reader.expect(' ')?;
+        let z = reader.read_double()?;
+        Ok(Arc::new(Vec3 { x, y, z }))
+    }
+}
+
+/// Removes every command registered from Lua, so that reloading the script starts over.
+pub fn clear(lua: &Lua) {
+    if let Some(mut commands) = lua.app_data_mut::<LuaCommands>() {
+        let mut root = commands.dispatcher.root.write();
+        for name in &commands.names {
+            root.children.remove(name);
+            root.literals.remove(name);
+        }
+        drop(root);
+        commands.names.clear();
+    }
+}
+
+pub fn register_globals(lua: &Lua, globals: &Table, dispatcher: Dispatcher) -> Result<()> {
+    lua.set_app_data(LuaCommands {
+        dispatcher,
+        names: HashSet::new(),
+    });
+
+    globals.set(
+        "register_command",
+        lua.create_function(|lua, (spec, handler): (String, Function)| {
+            let (name, parameters) = parse_spec(&spec)?;
+            let mut commands = lua
+                .app_data_mut::<LuaCommands>()
+                .ok_or_else(|| Error::runtime("commands are unavailable"))?;
+            if !commands.names.contains(&name)
+                && commands.dispatcher.root.read().children.contains_key(&name)
+            {
+                return Err(Error::runtime(format!("command {name} already exists")));
+            }
+
+            let node = build(&name, parameters, handler).build();
+            let mut root = commands.dispatcher.root.write();
+            root.children.remove(&name);
+            root.literals.remove(&name);
+            root.add_child(&Arc::new(parking_lot::RwLock::new(node)));
+            drop(root);
+            commands.names.insert(name);
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
+
+fn parse_spec(spec: &str) -> Result<(String, Vec<Parameter>)> {
+    let mut words = spec.split_whitespace();
+    let name = words
+        .next()
+        .ok_or_else(|| Error::runtime("command spec is empty"))?
+        .to_owned();
+    let parameters = words.map(parse_parameter).collect::<Result<Vec<_>>>()?;
+    for pair in parameters.windows(2) {
+        if matches!(pair[0].kind, Kind::Text) {
+            return Err(Error::runtime("text arguments must come last"));
+        }
+        if pair[0].optional && !pair[1].optional {
+            return Err(Error::runtime(
+                "required arguments can't follow optional ones",
+            ));
+        }
+    }
+    Ok((name, parameters))
+}
+
+fn parse_parameter(word: &str) -> Result<Parameter> {
+    let (inner, optional) = if let Some(inner) = word
+        .strip_prefix('<')
+        .and_then(|word| word.strip_suffix('>'))
+    {
+        (inner, false)
+    } else if let Some(inner) = word
+        .strip_prefix('[')
+        .and_then(|word| word.strip_suffix(']'))
+    {
+        (inner, true)
+    } else {
+        return Err(Error::runtime(format!(
+            "invalid argument {word}, expected <name:type> or [name:type]"
+        )));
+    };
+    let (name, kind) = inner.split_once(':').unwrap_or((inner, "word"));
+    let kind = match kind {
+        "word" => Kind::Word,
+        "string" => Kind::String,
+        "text" => Kind::Text,
+        "integer" => Kind::Integer,
+        "float" => Kind::Float,
+        "player" => Kind::Player,
+        "position" => Kind::Position,
+        _ => return Err(Error::runtime(format!("unknown argument type {kind}"))),
+    };
+    Ok(Parameter {
+        name: name.to_owned(),
+        kind,
+        optional,
+    })
+}
+
+fn build(
+    name: &str,
+    parameters: Vec<Parameter>,
+    handler: Function,
+) -> ArgumentBuilder<Mutex<CommandSource>> {
+    let required = parameters
+        .iter()
+        .position(|parameter| parameter.optional)
+        .unwrap_or(parameters.len());
+    let parameters = Arc::new(parameters);
+    let executes = {
+        let (name, parameters) = (name.to_owned(), parameters.clone());
+        move |ctx: &Ctx| execute(ctx, &name, &parameters, handler.clone())
+    };
+
+    let mut node = None;
+    for (index, parameter) in parameters.iter().enumerate().rev() {
+        let mut builder = match parameter.kind {
+            Kind::Word => argument(&parameter.name, word()),
+            Kind::String => argument(&parameter.name, string()),
+            Kind::Text => argument(&parameter.name, greedy_string()),
+            Kind::Integer => argument(&parameter.name, integer()),
+            Kind::Float => argument(&parameter.name, double()),
+            Kind::Player => argument(&parameter.name, PlayerArgument),
+            Kind::Position => argument(&parameter.name, PositionArgument),
+        };
+        if let Some(child) = node {
+            builder = builder.then(child);
+        }
+        if index + 1 >= required {
+            builder = builder.executes(executes.clone());
+        }
+        node = Some(builder);
+    }
+
+    let mut builder = literal(name).requires(allowed(name));
+    if let Some(child) = node {
+        builder = builder.then(child);
+    }
+    if required == 0 {
+        builder = builder.executes(executes);
+    }
+    builder
+}
+
+fn execute(ctx: &Ctx, name: &str, parameters: &[Parameter], handler: Function) -> i32 {
+    let arguments = parameters
+        .iter()
+        .filter_map(|parameter| {
+            let value = ctx.argument(&parameter.name)?;
+            Some((
+                parameter.name.clone(),
+                match parameter.kind {
+                    Kind::Word | Kind::String | Kind::Text | Kind::Player => {
+                        Argument::String(value.downcast_ref::<String>()?.clone())
+                    }
+                    Kind::Integer => Argument::Integer(*value.downcast_ref::<i32>()?),
+                    Kind::Float => Argument::Float(*value.downcast_ref::<f64>()?),
+                    Kind::Position => Argument::Position(value.downcast_ref::<Vec3>()?.clone()),
+                },
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    let (name, source) = (name.to_owned(), ctx.source.clone());
+    tokio::spawn(async move {
+        let (lua, sender) = {
+            let source = source.lock().await;
+            (source.state.lua.clone(), source.sender())
+        };
+        let call = async {
+            let table = lua.create_table()?;
+            for (name, value) in arguments {
+                table.set(name, value)?;
+            }
+            let reply = {
+                let source = source.clone();
+                lua.create_async_function(move |_, message: String| {
+                    let source = source.clone();
+                    async move {
+                        source.lock().await.reply(&message);
+                        Ok(())
+                    }
+                })?
+            };
+            handler
+                .call_async::<()>((table, reply, sender.map(|sender| sender.name)))
+                .await
+        };
+        if let Err(error) = call.await {
+            error!("failed to run lua command {name}: {error}");
+            source.lock().await.reply(&error.to_string());
+        }
+    });
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(spec: &str) -> Vec<(String, Kind, bool)> {
+        parse_spec(spec)
+            .unwrap()
+            .1
+            .into_iter()
+            .map(|parameter| (parameter.name, parameter.kind, parameter.optional))
+            .collect()
+    }
+
+    #[test]
+    fn parses_specs() {
+        assert_eq!(parse_spec("ping").unwrap().0, "ping");
+        assert!(parse_spec("ping").unwrap().1.is_empty());
+        assert_eq!(
+            kinds("give <target:player> <at:position> [count:integer] [note:text]"),
+            [
+                (String::from("target"), Kind::Player, false),
+                (String::from("at"), Kind::Position, false),
+                (String::from("count"), Kind::Integer, true),
+                (String::from("note"), Kind::Text, true),
+            ]
+        );
+        assert_eq!(
+            kinds("say <what> [how:string] [scale:float]"),
+            [
+                (String::from("what"), Kind::Word, false),
+                (String::from("how"), Kind::String, true),
+                (String::from("scale"), Kind::Float, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        for spec in [
+            "",
+            "   ",
+            "ping target",
+            "ping <target",
+            "ping <target:thing>",
+            "ping <message:text> <target>",
+            "ping [target] <message>",
+        ] {
+            assert!(parse_spec(spec).is_err(), "{spec:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn parses_players() {
+        let mut reader = StringReader::from("Steve_01 rest");
+        let name = PlayerArgument.parse(&mut reader).unwrap();
+        assert_eq!(name.downcast_ref::<String>().unwrap(), "Steve_01");
+        assert_eq!(reader.remaining(), " rest");
+
+        for input in ["", "a_name_that_is_too_long", "bad-name"] {
+            let mut reader = StringReader::from(input);
+            assert!(PlayerArgument.parse(&mut reader).is_err());
+            assert_eq!(reader.cursor, 0);
+        }
+    }
+
+    #[test]
+    fn parses_positions() {
+        let mut reader = StringReader::from("1 -2.5 300");
+        let position = PositionArgument.parse(&mut reader).unwrap();
+        let position = position.downcast_ref::<Vec3>().unwrap();
+        assert_eq!((position.x, position.y, position.z), (1.0, -2.5, 300.0));
+
+        for input in ["1 2", "1,2,3", "x y z"] {
+            assert!(
+                PositionArgument
+                    .parse(&mut StringReader::from(input))
+                    .is_err()
+            );
+        }
+    }
+}
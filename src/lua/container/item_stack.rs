@@ -2,10 +2,21 @@ use azalea::inventory::{
     self, ItemStackData,
     components::{Consumable, CustomName, Damage, Food, MaxDamage},
 };
-use mlua::{UserData, UserDataFields, UserDataMethods};
+use mlua::{Lua, LuaSerdeExt, Result, Table, UserData, UserDataFields, UserDataMethods};
 
 pub struct ItemStack(pub inventory::ItemStack);
 
+impl ItemStack {
+    /// A plain table of the kind, count and data components, for when the userdata can't be used.
+    pub fn to_table(&self, lua: &Lua) -> Result<Table> {
+        let table = lua.create_table()?;
+        table.set("kind", self.0.kind().to_string())?;
+        table.set("count", self.0.count())?;
+        table.set("components", lua.to_value(self.0.component_patch())?)?;
+        Ok(table)
+    }
+}
+
 impl UserData for ItemStack {
     fn add_fields<F: UserDataFields<Self>>(f: &mut F) {
         f.add_field_method_get("is_empty", |_, this| Ok(this.0.is_empty()));
@@ -76,3 +87,27 @@ impl UserData for ItemStack {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use azalea::registry::builtin::ItemKind;
+
+    use super::*;
+
+    #[test]
+    fn converts_to_table() {
+        let lua = Lua::new();
+        let item_stack = ItemStack(
+            inventory::ItemStack::new(ItemKind::DiamondSword, 1)
+                .with_component(Damage { amount: 3 }),
+        );
+        let table = item_stack.to_table(&lua).unwrap();
+        assert_eq!(
+            table.get::<String>("kind").unwrap(),
+            "minecraft:diamond_sword"
+        );
+        assert_eq!(table.get::<i32>("count").unwrap(), 1);
+        let components = table.get::<Table>("components").unwrap();
+        assert_eq!(components.get::<i32>("minecraft:damage").unwrap(), 3);
+    }
+}
@@ -1,35 +1,215 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use futures::executor::block_on;
-use mlua::{Function, Lua, Result, Table};
+use futures_locks::RwLock;
+use mlua::{Error, FromLuaMulti, Function, IntoLuaMulti, Lua, MultiValue, Result, Table};
+use parking_lot::Mutex;
+use tokio::{sync::mpsc, time::timeout};
 
-use crate::ListenerMap;
+use super::packet::CLIENTBOUND_IDS;
+use crate::{ListenerMap, events::dispatch};
 
+static WAITER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Event listeners by event type, along with which packets have any.
+#[derive(Default)]
+pub struct Listeners {
+    map: RwLock<HashMap<String, Arc<Vec<Listener>>>>,
+    packets: [AtomicU64; 4],
+}
+
+impl Listeners {
+    pub fn has_packet_listeners(&self, id: u32) -> bool {
+        self.packets
+            .get(id as usize / 64)
+            .is_some_and(|bits| bits.load(Ordering::Relaxed) & 1 << (id % 64) != 0)
+    }
+
+    pub async fn absorb(&self, other: &Self) {
+        let taken = std::mem::take(&mut *other.map.write().await);
+        for (event_type, listeners) in taken {
+            other.mark_packet(&event_type, false);
+            for listener in listeners.iter() {
+                add(self, event_type.clone(), listener.clone()).await;
+            }
+        }
+    }
+
+    fn mark_packet(&self, event_type: &str, listened: bool) {
+        let Some(&id) = event_type
+            .strip_prefix("packet:")
+            .and_then(|name| CLIENTBOUND_IDS.get(name))
+        else {
+            return;
+        };
+        let Some(bits) = self.packets.get(id as usize / 64) else {
+            return;
+        };
+        if listened {
+            bits.fetch_or(1 << (id % 64), Ordering::Relaxed);
+        } else {
+            bits.fetch_and(!(1 << (id % 64)), Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Listener {
+    pub id: String,
+    pub callback: Function,
+    pub priority: i32,
+    pub once: bool,
+    pub stats: Arc<Mutex<ListenerStats>>,
+}
+
+#[derive(Default)]
+pub struct ListenerStats {
+    pub invocations: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub runtime: Duration,
+    pub disabled: bool,
+}
+
+impl Listener {
+    fn new(callback: Function, id: Option<String>, priority: Option<i32>, once: bool) -> Self {
+        let id = id.unwrap_or_else(|| {
+            callback.info().name.unwrap_or_else(|| {
+                format!(
+                    "anonymous @ {}",
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis()
+                )
+            })
+        });
+        Self {
+            id,
+            callback,
+            priority: priority.unwrap_or_default(),
+            once,
+            stats: Arc::default(),
+        }
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.stats.lock().disabled
+    }
+
+    /// Calls the listener, recording how long it took and whether it failed.
+    pub async fn call<R: FromLuaMulti>(&self, data: impl IntoLuaMulti) -> Result<R> {
+        let start = Instant::now();
+        let result = self.callback.call_async(data).await;
+
+        let mut stats = self.stats.lock();
+        stats.invocations += 1;
+        stats.runtime += start.elapsed();
+        match &result {
+            Ok(_) => stats.consecutive_failures = 0,
+            Err(error) => {
+                stats.failures += 1;
+                stats.consecutive_failures += 1;
+                stats.last_error = Some(error.to_string());
+            }
+        }
+        drop(stats);
+        result
+    }
+}
+
+/// Returns the listeners for `event_type`, removing any that should only fire once.
+pub async fn take_listeners(
+    event_listeners: &ListenerMap,
+    event_type: &str,
+) -> Option<Arc<Vec<Listener>>> {
+    let listeners = event_listeners.map.read().await.get(event_type).cloned()?;
+    if !listeners.iter().any(|listener| listener.once) {
+        return Some(listeners);
+    }
+
+    let mut m = event_listeners.map.write().await;
+    let remaining = m.get_mut(event_type)?;
+    let listeners = remaining.clone();
+    Arc::make_mut(remaining).retain(|listener| !listener.once);
+    if remaining.is_empty() {
+        m.remove(event_type);
+        event_listeners.mark_packet(event_type, false);
+    }
+    Some(listeners)
+}
+
+async fn add(event_listeners: &Listeners, event_type: String, listener: Listener) {
+    let mut m = event_listeners.map.write().await;
+    event_listeners.mark_packet(&event_type, true);
+    let listeners = Arc::make_mut(m.entry(event_type).or_default());
+    let index = listeners
+        .iter()
+        .position(|other| other.priority < listener.priority)
+        .unwrap_or(listeners.len());
+    listeners.insert(index, listener);
+}
+
+async fn remove(event_listeners: &ListenerMap, event_type: &str, target_id: &str) {
+    let mut m = event_listeners.map.write().await;
+    let empty = m.get_mut(event_type).is_some_and(|listeners| {
+        Arc::make_mut(listeners).retain(|listener| target_id != listener.id);
+        listeners.is_empty()
+    });
+    if empty {
+        m.remove(event_type);
+        event_listeners.mark_packet(event_type, false);
+    }
+}
+
+#[allow(clippy::too_many_lines)]
 pub fn register_globals(lua: &Lua, globals: &Table, event_listeners: ListenerMap) -> Result<()> {
+    for (name, once) in [("add_listener", false), ("add_listener_once", true)] {
+        let m = event_listeners.clone();
+        globals.set(
+            name,
+            lua.create_function(
+                move |_,
+                      (event_type, callback, optional_id, priority): (
+                    String,
+                    Function,
+                    Option<String>,
+                    Option<i32>,
+                )| {
+                    let listener = Listener::new(callback, optional_id, priority, once);
+                    block_on(add(&m, event_type, listener));
+                    Ok(())
+                },
+            )?,
+        )?;
+    }
+
     let m = event_listeners.clone();
     globals.set(
-        "add_listener",
+        "add_packet_listener",
         lua.create_function(
-            move |_, (event_type, callback, optional_id): (String, Function, Option<String>)| {
-                let m = m.clone();
-                let id = optional_id.unwrap_or_else(|| {
-                    callback.info().name.unwrap_or_else(|| {
-                        format!(
-                            "anonymous @ {}",
-                            SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap_or_default()
-                                .as_millis()
-                        )
-                    })
-                });
-                tokio::spawn(async move {
-                    m.write()
-                        .await
-                        .entry(event_type)
-                        .or_default()
-                        .push((id, callback));
-                });
+            move |_,
+                  (packet_name, callback, optional_id, priority): (
+                String,
+                Function,
+                Option<String>,
+                Option<i32>,
+            )| {
+                if !CLIENTBOUND_IDS.contains_key(&packet_name) {
+                    return Err(Error::runtime(format!(
+                        "unknown clientbound packet {packet_name}"
+                    )));
+                }
+                let listener = Listener::new(callback, optional_id, priority, false);
+                block_on(add(&m, format!("packet:{packet_name}"), listener));
                 Ok(())
             },
         )?,
@@ -39,35 +219,96 @@ pub fn register_globals(lua: &Lua, globals: &Table, event_listeners: ListenerMap
     globals.set(
         "remove_listeners",
         lua.create_function(move |_, (event_type, target_id): (String, String)| {
-            let m = m.clone();
-            tokio::spawn(async move {
-                let mut m = m.write().await;
-                let empty = m.get_mut(&event_type).is_some_and(|listeners| {
-                    listeners.retain(|(id, _)| target_id != *id);
-                    listeners.is_empty()
-                });
-                if empty {
-                    m.remove(&event_type);
-                }
-            });
+            block_on(remove(&m, &event_type, &target_id));
             Ok(())
         })?,
     )?;
 
+    let m = event_listeners.clone();
+    globals.set(
+        "emit",
+        lua.create_function(move |lua, (event_type, data): (String, MultiValue)| {
+            if let Some(listeners) = block_on(take_listeners(&m, &event_type)) {
+                dispatch(lua, &m, &event_type, listeners, data);
+            }
+            Ok(())
+        })?,
+    )?;
+
+    let m = event_listeners.clone();
+    globals.set(
+        "wait_for_event",
+        lua.create_async_function(
+            move |lua,
+                  (event_type, filter, timeout_ms): (
+                String,
+                Option<Function>,
+                Option<u64>,
+            )| {
+                let m = m.clone();
+                async move {
+                    let (sender, mut receiver) = mpsc::unbounded_channel();
+                    let callback = lua.create_async_function(move |_, data: MultiValue| {
+                        let (filter, sender) = (filter.clone(), sender.clone());
+                        async move {
+                            if let Some(filter) = filter
+                                && !filter.call_async::<bool>(data.clone()).await?
+                            {
+                                return Ok(());
+                            }
+                            let _ = sender.send(data);
+                            Ok(())
+                        }
+                    })?;
+
+                    let id = format!(
+                        "wait_for_event @ {}",
+                        WAITER_ID.fetch_add(1, Ordering::Relaxed)
+                    );
+                    add(
+                        &m,
+                        event_type.clone(),
+                        Listener::new(callback, Some(id.clone()), None, false),
+                    )
+                    .await;
+                    let data = match timeout_ms {
+                        Some(duration) => timeout(Duration::from_millis(duration), receiver.recv())
+                            .await
+                            .ok()
+                            .flatten(),
+                        None => receiver.recv().await,
+                    };
+                    remove(&m, &event_type, &id).await;
+                    Ok(data.unwrap_or_default())
+                }
+            },
+        )?,
+    )?;
+
     globals.set(
         "get_listeners",
         lua.create_function(move |lua, (): ()| {
-            let m = block_on(event_listeners.read());
+            let m = block_on(event_listeners.map.read());
             let listeners_table = lua.create_table()?;
             for (event_type, callbacks) in m.iter() {
                 let type_listeners_table = lua.create_table()?;
-                for (id, callback) in callbacks {
-                    let info = callback.info();
+                for listener in callbacks.iter() {
+                    let info = listener.callback.info();
                     let table = lua.create_table()?;
                     table.set("name", info.name)?;
                     table.set("line_defined", info.line_defined)?;
                     table.set("source", info.source)?;
-                    type_listeners_table.set(id.to_owned(), table)?;
+                    table.set("priority", listener.priority)?;
+                    table.set("once", listener.once)?;
+                    let stats = listener.stats.lock();
+                    table.set("invocations", stats.invocations)?;
+                    table.set("failures", stats.failures)?;
+                    table.set("consecutive_failures", stats.consecutive_failures)?;
+                    table.set("last_error", stats.last_error.as_deref())?;
+                    table.set("runtime", stats.runtime.as_secs_f64())?;
+                    table.set("disabled", stats.disabled)?;
+                    drop(stats);
+                    type_listeners_table.set(listener.id.as_str(), table)?;
                 }
                 listeners_table.set(event_type.to_owned(), type_listeners_table)?;
             }
@@ -77,3 +318,41 @@ pub fn register_globals(lua: &Lua, globals: &Table, event_listeners: ListenerMap
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_packet_listeners() {
+        let lua = Lua::new();
+        let globals = lua.globals();
+        let listeners = ListenerMap::default();
+        register_globals(&lua, &globals, listeners.clone()).unwrap();
+        let id = CLIENTBOUND_IDS["keep_alive"];
+
+        assert!(!listeners.has_packet_listeners(id));
+        lua.load("add_packet_listener('keep_alive', function() end, 'a')")
+            .exec()
+            .unwrap();
+        lua.load("add_listener('packet:keep_alive', function() end, 'b')")
+            .exec()
+            .unwrap();
+        assert!(listeners.has_packet_listeners(id));
+        assert!(!listeners.has_packet_listeners(CLIENTBOUND_IDS["ping"]));
+
+        lua.load("remove_listeners('packet:keep_alive', 'a')")
+            .exec()
+            .unwrap();
+        assert!(listeners.has_packet_listeners(id));
+        lua.load("remove_listeners('packet:keep_alive', 'b')")
+            .exec()
+            .unwrap();
+        assert!(!listeners.has_packet_listeners(id));
+        assert!(
+            lua.load("add_packet_listener('nope', function() end)")
+                .exec()
+                .is_err()
+        );
+    }
+}
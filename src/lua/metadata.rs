@@ -0,0 +1,86 @@
+use azalea::entity::{EntityDataItem, EntityDataValue};
+use mlua::{IntoLua, Lua, Result, Value};
+
+use super::{container::item_stack::ItemStack, vec3::Vec3};
+
+#[derive(Clone)]
+pub struct EntityMetadata {
+    pub id: i32,
+    pub items: Vec<EntityDataItem>,
+    pub is_living: bool,
+}
+
+impl IntoLua for EntityMetadata {
+    fn into_lua(self, lua: &Lua) -> Result<Value> {
+        let table = lua.create_table()?;
+        let values = lua.create_table()?;
+        let items = lua.create_table()?;
+        table.set("id", self.id)?;
+
+        for item in self.items {
+            match (item.index, &item.value) {
+                (0, EntityDataValue::Byte(flags)) => {
+                    table.set("on_fire", flags & 0x01 != 0)?;
+                    table.set("crouching", flags & 0x02 != 0)?;
+                    table.set("sprinting", flags & 0x08 != 0)?;
+                    table.set("swimming", flags & 0x10 != 0)?;
+                    table.set("invisible", flags & 0x20 != 0)?;
+                    table.set("glowing", flags & 0x40 != 0)?;
+                    table.set("fall_flying", flags & 0x80 != 0)?;
+                }
+                (1, EntityDataValue::Int(air_supply)) => table.set("air_supply", *air_supply)?,
+                (2, EntityDataValue::OptionalFormattedText(name)) => {
+                    table.set("custom_name", name.as_ref().map(ToString::to_string))?;
+                }
+                (3, EntityDataValue::Boolean(visible)) => {
+                    table.set("custom_name_visible", *visible)?;
+                }
+                (4, EntityDataValue::Boolean(silent)) => table.set("silent", *silent)?,
+                (5, EntityDataValue::Boolean(no_gravity)) => {
+                    table.set("no_gravity", *no_gravity)?;
+                }
+                (6, EntityDataValue::Pose(pose)) => table.set("pose", *pose as u8)?,
+                (7, EntityDataValue::Int(ticks)) => table.set("ticks_frozen", *ticks)?,
+                (9, EntityDataValue::Float(health)) if self.is_living => {
+                    table.set("health", *health)?;
+                }
+                (_, EntityDataValue::ItemStack(item_stack)) => {
+                    items.set(item.index, ItemStack(item_stack.clone()).to_table(lua)?)?;
+                }
+                _ => (),
+            }
+            values.set(item.index, to_lua(lua, item.value)?)?;
+        }
+
+        table.set("values", values)?;
+        table.set("items", items)?;
+        Ok(Value::Table(table))
+    }
+}
+
+fn to_lua(lua: &Lua, value: EntityDataValue) -> Result<Value> {
+    match value {
+        EntityDataValue::Byte(byte) => byte.into_lua(lua),
+        EntityDataValue::Int(int) => int.into_lua(lua),
+        EntityDataValue::Long(long) => long.into_lua(lua),
+        EntityDataValue::Float(float) => float.into_lua(lua),
+        EntityDataValue::String(string) => string.into_lua(lua),
+        EntityDataValue::FormattedText(text) => text.to_string().into_lua(lua),
+        EntityDataValue::OptionalFormattedText(text) => {
+            text.map(|text| text.to_string()).into_lua(lua)
+        }
+        EntityDataValue::ItemStack(item_stack) => ItemStack(item_stack).into_lua(lua),
+        EntityDataValue::Boolean(boolean) => boolean.into_lua(lua),
+        EntityDataValue::BlockPos(position) => Vec3::from(position).into_lua(lua),
+        EntityDataValue::OptionalBlockPos(position) => position.map(Vec3::from).into_lua(lua),
+        EntityDataValue::OptionalLivingEntityReference(uuid) => {
+            uuid.map(|uuid| uuid.to_string()).into_lua(lua)
+        }
+        EntityDataValue::BlockState(state) | EntityDataValue::OptionalBlockState(state) => {
+            state.id().into_lua(lua)
+        }
+        EntityDataValue::OptionalUnsignedInt(int) => int.0.into_lua(lua),
+        EntityDataValue::Pose(pose) => (pose as u8).into_lua(lua),
+        _ => Ok(Value::Nil),
+    }
+}
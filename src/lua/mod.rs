@@ -1,10 +1,13 @@
 pub mod block;
 pub mod client;
+pub mod commands;
 pub mod container;
 pub mod direction;
 pub mod events;
 pub mod logging;
+pub mod metadata;
 pub mod nochatreports;
+pub mod packet;
 pub mod player;
 pub mod system;
 pub mod thread;
@@ -16,11 +19,47 @@ pub mod matrix;
 use std::{
     fmt::{self, Display, Formatter},
     io,
+    sync::Arc,
 };
 
-use mlua::{Lua, Table};
+use azalea::brigadier::prelude::CommandDispatcher;
+use futures::lock::Mutex;
+use log::warn;
+use mlua::{FromLuaMulti, Lua, Table};
 
-use crate::{ListenerMap, build_info::built};
+use crate::{ListenerMap, build_info::built, commands::CommandSource, config::Config};
+
+#[derive(Clone, Copy)]
+pub enum Frontend {
+    Chat,
+    Console,
+    Http,
+    #[cfg(feature = "matrix")]
+    Matrix,
+}
+
+impl Display for Frontend {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{}",
+            match self {
+                Self::Chat => "chat",
+                Self::Console => "console",
+                Self::Http => "http",
+                #[cfg(feature = "matrix")]
+                Self::Matrix => "matrix",
+            }
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct Sender {
+    pub name: String,
+    pub uuid: Option<String>,
+    pub frontend: Frontend,
+}
 
 #[derive(Debug)]
 pub enum Error {
@@ -53,12 +92,14 @@ pub fn register_globals(
     lua: &Lua,
     globals: &Table,
     event_listeners: ListenerMap,
+    commands: Arc<CommandDispatcher<Mutex<CommandSource>>>,
 ) -> mlua::Result<()> {
     globals.set("CARGO_PKG_VERSION", env!("CARGO_PKG_VERSION"))?;
     globals.set("GIT_COMMIT_HASH", built::GIT_COMMIT_HASH)?;
     globals.set("GIT_COMMIT_HASH_SHORT", built::GIT_COMMIT_HASH_SHORT)?;
 
     block::register_globals(lua, globals)?;
+    commands::register_globals(lua, globals, commands)?;
     events::register_globals(lua, globals, event_listeners)?;
     logging::register_globals(lua, globals)?;
     nochatreports::register_globals(lua, globals)?;
@@ -66,7 +107,8 @@ pub fn register_globals(
     thread::register_globals(lua, globals)
 }
 
-pub fn reload(lua: &Lua, sender: Option<String>) -> Result<(), Error> {
+pub fn reload(lua: &Lua, sender: Option<Sender>) -> Result<(), Error> {
+    commands::clear(lua);
     lua.load(
         &std::fs::read_to_string(
             lua.globals()
@@ -77,18 +119,29 @@ pub fn reload(lua: &Lua, sender: Option<String>) -> Result<(), Error> {
     )
     .set_environment(create_env(lua, sender)?)
     .exec()
-    .map_err(Error::LoadChunk)
+    .map_err(Error::LoadChunk)?;
+
+    if let Some(config) = lua.app_data_ref::<Config>()
+        && let Err(error) = config.merge(lua, &lua.globals())
+    {
+        warn!("failed to merge configuration after reload: {error:?}");
+    }
+    Ok(())
 }
 
-pub async fn eval(lua: &Lua, code: &str, sender: Option<String>) -> Result<String, Error> {
+pub async fn eval<R: FromLuaMulti>(
+    lua: &Lua,
+    code: &str,
+    sender: Option<Sender>,
+) -> Result<R, Error> {
     lua.load(code)
         .set_environment(create_env(lua, sender)?)
-        .eval_async::<String>()
+        .eval_async::<R>()
         .await
         .map_err(Error::EvalChunk)
 }
 
-pub async fn exec(lua: &Lua, code: &str, sender: Option<String>) -> Result<(), Error> {
+pub async fn exec(lua: &Lua, code: &str, sender: Option<Sender>) -> Result<(), Error> {
     lua.load(code)
         .set_environment(create_env(lua, sender)?)
         .exec_async()
@@ -96,8 +149,26 @@ pub async fn exec(lua: &Lua, code: &str, sender: Option<String>) -> Result<(), E
         .map_err(Error::ExecChunk)
 }
 
-fn create_env(lua: &Lua, sender: Option<String>) -> Result<Table, Error> {
+fn create_env(lua: &Lua, sender: Option<Sender>) -> Result<Table, Error> {
     let globals = lua.globals();
-    globals.set("sender", sender).map_err(Error::CreateEnv)?;
-    Ok(globals)
+    let env = lua.create_table().map_err(Error::CreateEnv)?;
+    if let Some(sender) = sender {
+        env.raw_set("sender", sender.name)
+            .map_err(Error::CreateEnv)?;
+        env.raw_set("sender_uuid", sender.uuid)
+            .map_err(Error::CreateEnv)?;
+        env.raw_set("frontend", sender.frontend.to_string())
+            .map_err(Error::CreateEnv)?;
+    }
+
+    let metatable = lua.create_table().map_err(Error::CreateEnv)?;
+    metatable
+        .set("__index", &globals)
+        .map_err(Error::CreateEnv)?;
+    metatable
+        .set("__newindex", globals)
+        .map_err(Error::CreateEnv)?;
+    env.set_metatable(Some(metatable))
+        .map_err(Error::CreateEnv)?;
+    Ok(env)
 }
@@ -0,0 +1,725 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::{Cursor, Write},
+    sync::LazyLock,
+};
+
+use azalea::{
+    BlockPos,
+    buf::{AzBuf, AzBufVar},
+    protocol::{
+        packets::{
+            ProtocolPacket,
+            game::{ClientboundGamePacket, ServerboundGamePacket},
+        },
+        read::ReadPacketError,
+    },
+};
+use mlua::{Error, Lua, Result, Table, Value};
+
+use super::vec3::Vec3;
+
+pub static CLIENTBOUND_IDS: LazyLock<HashMap<String, u32>> =
+    LazyLock::new(packet_ids::<ClientboundGamePacket>);
+pub static SERVERBOUND_IDS: LazyLock<HashMap<String, u32>> =
+    LazyLock::new(packet_ids::<ServerboundGamePacket>);
+
+fn packet_ids<P: ProtocolPacket>() -> HashMap<String, u32> {
+    let mut ids = HashMap::new();
+    for id in 0..=u8::MAX.into() {
+        match P::read(id, &mut Cursor::new(&[])) {
+            Ok(packet) => {
+                ids.insert(packet.name().to_owned(), id);
+            }
+            Err(error) => {
+                if let ReadPacketError::Parse { packet_name, .. } = *error {
+                    ids.insert(packet_name, id);
+                }
+            }
+        }
+    }
+    ids
+}
+
+const DECODED_PACKETS: [&str; 19] = [
+    "animate",
+    "container_close",
+    "entity_event",
+    "forget_level_chunk",
+    "game_event",
+    "hurt_animation",
+    "keep_alive",
+    "ping",
+    "pong_response",
+    "remove_entities",
+    "set_chunk_cache_center",
+    "set_chunk_cache_radius",
+    "set_display_objective",
+    "set_entity_link",
+    "set_experience",
+    "set_health",
+    "set_held_slot",
+    "set_simulation_distance",
+    "take_item_entity",
+];
+
+/// Converts a clientbound packet into a table, decoding fields only for `DECODED_PACKETS`.
+pub fn to_table(lua: &Lua, packet: &ClientboundGamePacket) -> Result<Table> {
+    let fields = if DECODED_PACKETS.contains(&packet.name()) {
+        match DebugParser::new(lua, packet).value()? {
+            Value::Table(table) => Some(table),
+            other => {
+                let table = lua.create_table()?;
+                table.set("value", other)?;
+                Some(table)
+            }
+        }
+    } else {
+        None
+    };
+    let mut data = Vec::new();
+    packet.write(&mut data).map_err(Error::external)?;
+
+    let table = lua.create_table()?;
+    table.set("id", packet.id())?;
+    table.set("name", packet.name())?;
+    table.set("fields", fields)?;
+    table.set("data", lua.create_string(data)?)?;
+    Ok(table)
+}
+
+/// Builds a serverbound packet from raw bytes or a list of `{ type, value }` pairs.
+pub fn build(lua: &Lua, name: &str, data: Value) -> Result<ServerboundGamePacket> {
+    let id = *SERVERBOUND_IDS
+        .get(name)
+        .ok_or_else(|| Error::runtime(format!("unknown serverbound packet {name}")))?;
+    let bytes = match data {
+        Value::Nil => Vec::new(),
+        Value::String(string) => string.as_bytes().to_vec(),
+        Value::Table(table) => {
+            let mut buf = Vec::new();
+            for field in table.sequence_values::<Table>() {
+                let field = field?;
+                encode(lua, &mut buf, &field.get::<String>(1)?, field.get(2)?)?;
+            }
+            buf
+        }
+        other => {
+            return Err(Error::FromLuaConversionError {
+                from: other.type_name(),
+                to: "packet data".to_string(),
+                message: None,
+            });
+        }
+    };
+
+    ServerboundGamePacket::read(id, &mut Cursor::new(bytes.as_slice())).map_err(Error::external)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn encode(lua: &Lua, buf: &mut Vec<u8>, kind: &str, value: Value) -> Result<()> {
+    match kind {
+        "bool" => lua.unpack::<bool>(value)?.azalea_write(buf),
+        "byte" => lua.unpack::<i8>(value)?.azalea_write(buf),
+        "ubyte" => lua.unpack::<u8>(value)?.azalea_write(buf),
+        "short" => lua.unpack::<i16>(value)?.azalea_write(buf),
+        "ushort" => lua.unpack::<u16>(value)?.azalea_write(buf),
+        "int" => lua.unpack::<i32>(value)?.azalea_write(buf),
+        "long" => lua.unpack::<i64>(value)?.azalea_write(buf),
+        "float" => lua.unpack::<f32>(value)?.azalea_write(buf),
+        "double" => lua.unpack::<f64>(value)?.azalea_write(buf),
+        "varint" => lua.unpack::<i32>(value)?.azalea_write_var(buf),
+        "varlong" => lua.unpack::<i64>(value)?.azalea_write_var(buf),
+        "string" => lua.unpack::<String>(value)?.azalea_write(buf),
+        "bytes" => buf.write_all(&lua.unpack::<mlua::String>(value)?.as_bytes()),
+        "position" => {
+            let position = lua.unpack::<Vec3>(value)?;
+            BlockPos::new(
+                position.x.floor() as i32,
+                position.y.floor() as i32,
+                position.z.floor() as i32,
+            )
+            .azalea_write(buf)
+        }
+        "uuid" => {
+            let uuid = u128::from_str_radix(&lua.unpack::<String>(value)?.replace('-', ""), 16)
+                .map_err(Error::external)?;
+            buf.write_all(&uuid.to_be_bytes())
+        }
+        _ => return Err(Error::runtime(format!("unknown field type {kind}"))),
+    }
+    .map_err(Error::external)
+}
+
+struct DebugParser<'a> {
+    lua: &'a Lua,
+    input: Vec<char>,
+    position: usize,
+}
+
+impl<'a> DebugParser<'a> {
+    fn new(lua: &'a Lua, value: &impl Debug) -> Self {
+        Self {
+            lua,
+            input: format!("{value:?}").chars().collect(),
+            position: 0,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        while self
+            .input
+            .get(self.position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.position += 1;
+        }
+        self.input.get(self.position).copied()
+    }
+
+    fn at_delimiter(&self, position: usize) -> bool {
+        match self.input.get(position) {
+            None => true,
+            Some(':') => self
+                .input
+                .get(position + 1)
+                .is_none_or(|c| c.is_whitespace()),
+            Some(&c) => c.is_whitespace() || matches!(c, ',' | ')' | ']' | '}'),
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('"') => self.string('"'),
+            Some('\'') => self.string('\''),
+            Some('[') => self.collection(']').map(Value::Table),
+            Some('(') => self.collection(')').map(Value::Table),
+            Some('{') => self.collection('}').map(Value::Table),
+            Some(c) if c.is_alphabetic() || c == '_' => self.named(),
+            Some(_) => self.token(),
+            None => Ok(Value::Nil),
+        }
+    }
+
+    fn string(&mut self, quote: char) -> Result<Value> {
+        self.position += 1;
+        let mut string = String::new();
+        while let Some(&c) = self.input.get(self.position) {
+            self.position += 1;
+            match c {
+                '\\' => {
+                    let escaped = self.input.get(self.position).copied().unwrap_or('\\');
+                    self.position += 1;
+                    string.push(match escaped {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        '0' => '\0',
+                        'u' => {
+                            let start = self.position + 1;
+                            let end = self.input[start..]
+                                .iter()
+                                .position(|&c| c == '}')
+                                .map_or(start, |offset| start + offset);
+                            self.position = end + 1;
+                            u32::from_str_radix(
+                                &self.input[start..end].iter().collect::<String>(),
+                                16,
+                            )
+                            .ok()
+                            .and_then(char::from_u32)
+                            .unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        other => other,
+                    });
+                }
+                c if c == quote => break,
+                c => string.push(c),
+            }
+        }
+        self.lua.create_string(string).map(Value::String)
+    }
+
+    fn collection(&mut self, close: char) -> Result<Table> {
+        self.position += 1;
+        let table = self.lua.create_table()?;
+        let mut index = 1;
+        while !self.eat(close) && self.peek().is_some() {
+            if self.eat('.') {
+                self.eat('.');
+            } else {
+                let value = self.value()?;
+                if self.eat(':') {
+                    let key = value;
+                    let value = self.value()?;
+                    if !key.is_nil() {
+                        table.raw_set(key, value)?;
+                    }
+                } else {
+                    table.raw_set(index, value)?;
+                    index += 1;
+                }
+            }
+            self.eat(',');
+        }
+        Ok(table)
+    }
+
+    fn named(&mut self) -> Result<Value> {
+        let start = self.position;
+        loop {
+            match self.input.get(self.position) {
+                Some(&c) if c.is_alphanumeric() || c == '_' => self.position += 1,
+                Some(':') if self.input.get(self.position + 1) == Some(&':') => {
+                    self.position += 2;
+                }
+                _ => break,
+            }
+        }
+        let name = self.input[start..self.position].iter().collect::<String>();
+
+        if self.input.get(self.position) == Some(&'(') {
+            let fields = self.collection(')')?;
+            return Ok(
+                if fields.raw_len() == 1 && fields.pairs::<Value, Value>().count() == 1 {
+                    fields.raw_get(1)?
+                } else {
+                    Value::Table(fields)
+                },
+            );
+        }
+        if !self.at_delimiter(self.position) {
+            self.position = start;
+            return self.token();
+        }
+        if self.peek() == Some('{') {
+            return self.collection('}').map(Value::Table);
+        }
+
+        Ok(match name.as_str() {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            "None" => Value::Nil,
+            "NaN" => Value::Number(f64::NAN),
+            "inf" => Value::Number(f64::INFINITY),
+            _ => Value::String(self.lua.create_string(name)?),
+        })
+    }
+
+    fn token(&mut self) -> Result<Value> {
+        let start = self.position;
+        while !self.at_delimiter(self.position) {
+            self.position += 1;
+        }
+        if self.position == start {
+            self.position += 1;
+            return Ok(Value::Nil);
+        }
+
+        let token = self.input[start..self.position].iter().collect::<String>();
+        Ok(if let Ok(integer) = token.parse::<i64>() {
+            Value::Integer(integer)
+        } else if let Ok(number) = token.parse::<f64>() {
+            Value::Number(number)
+        } else {
+            Value::String(self.lua.create_string(token)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fmt};
+
+    use azalea::{
+        FormattedText,
+        core::{entity_id::MinecraftEntityId, position::ChunkPos},
+        protocol::packets::game::{
+            c_animate::{AnimationAction, ClientboundAnimate},
+            c_container_close::ClientboundContainerClose,
+            c_entity_event::ClientboundEntityEvent,
+            c_forget_level_chunk::ClientboundForgetLevelChunk,
+            c_game_event::{ClientboundGameEvent, EventType},
+            c_hurt_animation::ClientboundHurtAnimation,
+            c_keep_alive::ClientboundKeepAlive,
+            c_ping::ClientboundPing,
+            c_pong_response::ClientboundPongResponse,
+            c_remove_entities::ClientboundRemoveEntities,
+            c_set_chunk_cache_center::ClientboundSetChunkCacheCenter,
+            c_set_chunk_cache_radius::ClientboundSetChunkCacheRadius,
+            c_set_display_objective::{ClientboundSetDisplayObjective, DisplaySlot},
+            c_set_entity_link::ClientboundSetEntityLink,
+            c_set_experience::ClientboundSetExperience,
+            c_set_health::ClientboundSetHealth,
+            c_set_held_slot::ClientboundSetHeldSlot,
+            c_set_simulation_distance::ClientboundSetSimulationDistance,
+            c_set_title_text::ClientboundSetTitleText,
+            c_take_item_entity::ClientboundTakeItemEntity,
+            s_keep_alive::ServerboundKeepAlive,
+        },
+    };
+    use mlua::Function;
+
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    enum Kind {
+        Unit,
+        Pair(i32, i32),
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Wrapper(u8);
+
+    struct Partial;
+
+    impl Debug for Partial {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Partial")
+                .field("id", &Identifier)
+                .finish_non_exhaustive()
+        }
+    }
+
+    struct Identifier;
+
+    impl Debug for Identifier {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "minecraft:stone")
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Example {
+        name: String,
+        count: i32,
+        ratio: f64,
+        flag: bool,
+        missing: Option<u8>,
+        present: Option<u8>,
+        list: Vec<i8>,
+        tuple: (u8, &'static str),
+        unit: Kind,
+        pair: Kind,
+        wrapped: Wrapper,
+        partial: Partial,
+    }
+
+    fn parse(lua: &Lua, value: &impl Debug) -> Value {
+        DebugParser::new(lua, value).value().unwrap()
+    }
+
+    #[test]
+    fn parses_structs() {
+        let lua = Lua::new();
+        let Value::Table(table) = parse(
+            &lua,
+            &Example {
+                name: String::from("a \"b\"\n\u{1}"),
+                count: -5,
+                ratio: 1.5,
+                flag: true,
+                missing: None,
+                present: Some(7),
+                list: vec![1, -2, 3],
+                tuple: (4, "four"),
+                unit: Kind::Unit,
+                pair: Kind::Pair(1, 2),
+                wrapped: Wrapper(9),
+                partial: Partial,
+            },
+        ) else {
+            panic!("expected a table");
+        };
+
+        assert_eq!(table.get::<String>("name").unwrap(), "a \"b\"\n\u{1}");
+        assert_eq!(table.get::<Value>("count").unwrap(), Value::Integer(-5));
+        assert_eq!(table.get::<Value>("ratio").unwrap(), Value::Number(1.5));
+        assert_eq!(table.get::<Value>("flag").unwrap(), Value::Boolean(true));
+        assert!(table.get::<Value>("missing").unwrap().is_nil());
+        assert_eq!(table.get::<Value>("present").unwrap(), Value::Integer(7));
+        assert_eq!(table.get::<Vec<i8>>("list").unwrap(), [1, -2, 3]);
+        let tuple = table.get::<Table>("tuple").unwrap();
+        assert_eq!(tuple.get::<u8>(1).unwrap(), 4);
+        assert_eq!(tuple.get::<String>(2).unwrap(), "four");
+        assert_eq!(table.get::<String>("unit").unwrap(), "Unit");
+        assert_eq!(table.get::<Vec<i32>>("pair").unwrap(), [1, 2]);
+        assert_eq!(table.get::<Value>("wrapped").unwrap(), Value::Integer(9));
+        let partial = table.get::<Table>("partial").unwrap();
+        assert_eq!(partial.get::<String>("id").unwrap(), "minecraft:stone");
+        assert_eq!(partial.pairs::<Value, Value>().count(), 1);
+    }
+
+    #[test]
+    fn parses_special_floats() {
+        let lua = Lua::new();
+        let Value::Number(nan) = parse(&lua, &f64::NAN) else {
+            panic!("expected a number");
+        };
+        assert!(nan.is_nan());
+        assert_eq!(parse(&lua, &f64::INFINITY), Value::Number(f64::INFINITY));
+        assert_eq!(
+            parse(&lua, &f64::NEG_INFINITY),
+            Value::Number(f64::NEG_INFINITY)
+        );
+    }
+
+    #[test]
+    fn parses_maps() {
+        let lua = Lua::new();
+        let Value::Table(table) = parse(&lua, &HashMap::from([("a", 1), ("b", 2)])) else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.get::<i32>("a").unwrap(), 1);
+        assert_eq!(table.get::<i32>("b").unwrap(), 2);
+    }
+
+    #[test]
+    fn packet_ids_are_unique() {
+        for ids in [&*CLIENTBOUND_IDS, &*SERVERBOUND_IDS] {
+            assert!(ids.len() > 50);
+            assert_eq!(ids.values().collect::<HashSet<_>>().len(), ids.len());
+        }
+    }
+
+    #[test]
+    fn packet_ids_match_packets() {
+        let clientbound = ClientboundGamePacket::KeepAlive(ClientboundKeepAlive { id: 1 });
+        assert_eq!(CLIENTBOUND_IDS["keep_alive"], clientbound.id());
+        let serverbound = ServerboundGamePacket::KeepAlive(ServerboundKeepAlive { id: 1 });
+        assert_eq!(SERVERBOUND_IDS["keep_alive"], serverbound.id());
+        assert!(SERVERBOUND_IDS.contains_key("chat_command"));
+    }
+
+    #[test]
+    fn builds_packets() {
+        let lua = Lua::new();
+        let fields = lua.load("{ { 'long', 42 } }").eval::<Value>().unwrap();
+        assert_eq!(
+            build(&lua, "keep_alive", fields).unwrap(),
+            ServerboundGamePacket::KeepAlive(ServerboundKeepAlive { id: 42 })
+        );
+        assert!(build(&lua, "not_a_packet", Value::Nil).is_err());
+        assert!(build(&lua, "keep_alive", Value::Nil).is_err());
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    fn decodes_listed_packets() {
+        let lua = Lua::new();
+        let equal = lua
+            .load(
+                r"
+                local function equal(a, b)
+                    if type(a) ~= 'table' or type(b) ~= 'table' then
+                        return a == b
+                    end
+                    for key, value in pairs(a) do
+                        if not equal(value, b[key]) then
+                            return false
+                        end
+                    end
+                    for key in pairs(b) do
+                        if a[key] == nil then
+                            return false
+                        end
+                    end
+                    return true
+                end
+                return equal
+                ",
+            )
+            .eval::<Function>()
+            .unwrap();
+        let cases = [
+            (
+                ClientboundGamePacket::Animate(ClientboundAnimate {
+                    id: MinecraftEntityId(3),
+                    action: AnimationAction::SwingMainHand,
+                }),
+                "{ id = 3, action = 'SwingMainHand' }",
+            ),
+            (
+                ClientboundGamePacket::ContainerClose(ClientboundContainerClose {
+                    container_id: 1,
+                }),
+                "{ container_id = 1 }",
+            ),
+            (
+                ClientboundGamePacket::EntityEvent(ClientboundEntityEvent {
+                    entity_id: MinecraftEntityId(3),
+                    event_id: 2,
+                }),
+                "{ entity_id = 3, event_id = 2 }",
+            ),
+            (
+                ClientboundGamePacket::ForgetLevelChunk(ClientboundForgetLevelChunk {
+                    pos: ChunkPos::new(1, -2),
+                }),
+                "{ pos = { x = 1, z = -2 } }",
+            ),
+            (
+                ClientboundGamePacket::GameEvent(ClientboundGameEvent {
+                    event: EventType::StartRaining,
+                    param: 0.5,
+                }),
+                "{ event = 'StartRaining', param = 0.5 }",
+            ),
+            (
+                ClientboundGamePacket::HurtAnimation(ClientboundHurtAnimation {
+                    id: MinecraftEntityId(3),
+                    yaw: 90.0,
+                }),
+                "{ id = 3, yaw = 90 }",
+            ),
+            (
+                ClientboundGamePacket::KeepAlive(ClientboundKeepAlive { id: 42 }),
+                "{ id = 42 }",
+            ),
+            (
+                ClientboundGamePacket::Ping(ClientboundPing { id: 7 }),
+                "{ id = 7 }",
+            ),
+            (
+                ClientboundGamePacket::PongResponse(ClientboundPongResponse { time: 9 }),
+                "{ time = 9 }",
+            ),
+            (
+                ClientboundGamePacket::RemoveEntities(ClientboundRemoveEntities {
+                    entity_ids: vec![MinecraftEntityId(1), MinecraftEntityId(2)],
+                }),
+                "{ entity_ids = { 1, 2 } }",
+            ),
+            (
+                ClientboundGamePacket::SetChunkCacheCenter(ClientboundSetChunkCacheCenter {
+                    x: -4,
+                    z: 5,
+                }),
+                "{ x = -4, z = 5 }",
+            ),
+            (
+                ClientboundGamePacket::SetChunkCacheRadius(ClientboundSetChunkCacheRadius {
+                    radius: 8,
+                }),
+                "{ radius = 8 }",
+            ),
+            (
+                ClientboundGamePacket::SetDisplayObjective(ClientboundSetDisplayObjective {
+                    slot: DisplaySlot::Sidebar,
+                    objective_name: String::from("kills: \"all\""),
+                }),
+                "{ slot = 'Sidebar', objective_name = 'kills: \"all\"' }",
+            ),
+            (
+                ClientboundGamePacket::SetEntityLink(ClientboundSetEntityLink {
+                    source_id: MinecraftEntityId(1),
+                    dest_id: MinecraftEntityId(-1),
+                }),
+                "{ source_id = 1, dest_id = -1 }",
+            ),
+            (
+                ClientboundGamePacket::SetExperience(ClientboundSetExperience {
+                    experience_progress: 0.5,
+                    experience_level: 3,
+                    total_experience: 40,
+                }),
+                "{ experience_progress = 0.5, experience_level = 3, total_experience = 40 }",
+            ),
+            (
+                ClientboundGamePacket::SetHeldSlot(ClientboundSetHeldSlot { slot: 4 }),
+                "{ slot = 4 }",
+            ),
+            (
+                ClientboundGamePacket::SetHealth(ClientboundSetHealth {
+                    health: 20.0,
+                    food: 18,
+                    saturation: 5.5,
+                }),
+                "{ health = 20, food = 18, saturation = 5.5 }",
+            ),
+            (
+                ClientboundGamePacket::SetSimulationDistance(ClientboundSetSimulationDistance {
+                    simulation_distance: 12,
+                }),
+                "{ simulation_distance = 12 }",
+            ),
+            (
+                ClientboundGamePacket::TakeItemEntity(ClientboundTakeItemEntity {
+                    item_id: 5,
+                    player_id: MinecraftEntityId(6),
+                    amount: 2,
+                }),
+                "{ item_id = 5, player_id = 6, amount = 2 }",
+            ),
+        ];
+
+        let mut names = cases
+            .iter()
+            .map(|(packet, _)| packet.name())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, DECODED_PACKETS);
+        for (packet, expected) in cases {
+            let fields = to_table(&lua, &packet)
+                .unwrap()
+                .get::<Table>("fields")
+                .unwrap();
+            let expected = lua.load(expected).eval::<Table>().unwrap();
+            assert!(
+                equal.call::<bool>((fields, expected)).unwrap(),
+                "unexpected fields for {}",
+                packet.name()
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_other_packets_undecoded() {
+        let lua = Lua::new();
+        let table = to_table(
+            &lua,
+            &ClientboundGamePacket::SetTitleText(ClientboundSetTitleText {
+                text: FormattedText::from("hi"),
+            }),
+        )
+        .unwrap();
+        assert!(table.get::<Value>("fields").unwrap().is_nil());
+        assert!(table.get::<mlua::String>("data").is_ok());
+    }
+
+    #[test]
+    fn converts_packets_to_tables() {
+        let lua = Lua::new();
+        let table = to_table(
+            &lua,
+            &ClientboundGamePacket::KeepAlive(ClientboundKeepAlive { id: 42 }),
+        )
+        .unwrap();
+        assert_eq!(table.get::<String>("name").unwrap(), "keep_alive");
+        assert_eq!(
+            table.get::<u32>("id").unwrap(),
+            CLIENTBOUND_IDS["keep_alive"]
+        );
+        assert_eq!(
+            table
+                .get::<Table>("fields")
+                .unwrap()
+                .get::<u64>("id")
+                .unwrap(),
+            42
+        );
+    }
+}
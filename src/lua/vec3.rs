@@ -1,4 +1,8 @@
-use azalea::{BlockPos, entity::Position};
+use azalea::{
+    BlockPos,
+    core::delta::{PositionDelta8, PositionDeltaTrait},
+    entity::Position,
+};
 use mlua::{Error, FromLua, IntoLua, Lua, Result, Value};
 
 #[derive(Clone)]
@@ -48,6 +52,16 @@ impl From<BlockPos> for Vec3 {
     }
 }
 
+impl From<&PositionDelta8> for Vec3 {
+    fn from(d: &PositionDelta8) -> Self {
+        Self {
+            x: d.x(),
+            y: d.y(),
+            z: d.z(),
+        }
+    }
+}
+
 impl FromLua for Vec3 {
     fn from_lua(value: Value, _lua: &Lua) -> Result<Self> {
         if let Value::Table(table) = value {
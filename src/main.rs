@@ -3,12 +3,19 @@
 
 mod arguments;
 mod build_info;
+mod chat;
 mod commands;
+mod config;
 mod events;
 mod hacks;
 mod http;
+mod journal;
 mod lua;
 mod particle;
+mod permissions;
+mod reconnect;
+mod repl;
+mod watcher;
 
 #[cfg(feature = "matrix")]
 mod matrix;
@@ -20,30 +27,36 @@ use std::{
     collections::HashMap,
     env,
     fs::{OpenOptions, read_to_string},
-    sync::Arc,
+    path::Path,
+    sync::{Arc, atomic::AtomicBool},
 };
 
 use anyhow::{Context, Result, bail};
 use arguments::Arguments;
 use azalea::{
-    DefaultPlugins, bot::DefaultBotPlugins, brigadier::prelude::CommandDispatcher, prelude::*,
+    DefaultPlugins,
+    bot::DefaultBotPlugins,
+    brigadier::prelude::CommandDispatcher,
+    prelude::*,
+    swarm::{DefaultSwarmPlugins, prelude::*},
 };
 use bevy_app::PluginGroup;
 use bevy_log::{
     LogPlugin,
-    tracing_subscriber::{Layer, fmt::layer},
+    tracing_subscriber::{self, EnvFilter, Layer, fmt::layer},
 };
+use chat::ChatQueue;
 use clap::Parser;
 use commands::{CommandSource, register};
+use config::Config;
 use futures::lock::Mutex;
-use futures_locks::RwLock;
+use http::stream::EventStream;
+use journal::Journal;
 use log::debug;
-use mlua::{Function, Lua};
+use lua::events::Listeners;
+use mlua::{Lua, Table};
 #[cfg(feature = "replay")]
-use {
-    mlua::Table,
-    replay::{plugin::RecordPlugin, recorder::Recorder},
-};
+use replay::{plugin::RecordPlugin, recorder::Recorder};
 
 use crate::hacks::HacksPlugin;
 
@@ -51,28 +64,42 @@ use crate::hacks::HacksPlugin;
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-type ListenerMap = Arc<RwLock<HashMap<String, Vec<(String, Function)>>>>;
+type ListenerMap = Arc<Listeners>;
+type BotListeners = Arc<parking_lot::Mutex<HashMap<String, ListenerMap>>>;
 
-#[derive(Default, Clone, Component)]
+#[derive(Default, Clone, Component, Resource)]
 struct State {
+    name: String,
     lua: Arc<Lua>,
+    chat: ChatQueue,
     event_listeners: ListenerMap,
     commands: Arc<CommandDispatcher<Mutex<CommandSource>>>,
+    event_stream: EventStream,
+    journal: Option<Journal>,
+    ticking: Arc<AtomicBool>,
+    bots: Arc<Vec<(String, ListenerMap)>>,
 }
 
+#[allow(clippy::too_many_lines)]
 #[tokio::main]
 async fn main() -> Result<()> {
     #[cfg(feature = "console-subscriber")]
     console_subscriber::init();
 
     let args = Arguments::parse();
-    let event_listeners = Arc::new(RwLock::new(HashMap::new()));
+    let event_listeners = ListenerMap::default();
     let lua = unsafe { Lua::unsafe_new() };
     let globals = lua.globals();
-    lua::register_globals(&lua, &globals, event_listeners.clone())?;
+    let mut commands = CommandDispatcher::new();
+    register(&mut commands);
+    let commands = Arc::new(commands);
+    lua::register_globals(&lua, &globals, event_listeners.clone(), commands.clone())?;
+    let bot_listeners = register_bots(&lua, &globals)?;
+    let config = Config::load(args.config.as_deref())?;
+    config.apply(&lua, &globals)?;
 
-    if let Some(path) = args.script {
-        globals.set("SCRIPT_PATH", &*path)?;
+    if let Some(path) = &args.script {
+        globals.set("SCRIPT_PATH", &**path)?;
         lua.load(read_to_string(path)?).exec()?;
     } else if let Some(code) = ["main.lua", "errornowatcher.lua"].iter().find_map(|path| {
         debug!("trying to load code from {path}");
@@ -81,19 +108,21 @@ async fn main() -> Result<()> {
     }) {
         lua.load(code).exec()?;
     }
-    if let Some(code) = args.exec {
+    if let Some(code) = &args.exec {
         lua.load(code).exec()?;
     }
+    config.merge(&lua, &globals)?;
+    lua.set_app_data(config);
+    config::apply_arguments(&args, &globals)?;
+
+    if let Some(path) = &args.replay_journal {
+        return replay_journal(path, lua, &globals, event_listeners, &bot_listeners).await;
+    }
+    config::validate(&lua, &globals)?;
 
     let server = globals
         .get::<String>("Server")
         .context("lua globals missing Server variable")?;
-    let username = globals
-        .get::<String>("Username")
-        .context("lua globals missing Username variable")?;
-
-    let mut commands = CommandDispatcher::new();
-    register(&mut commands);
 
     let default_plugins = if cfg!(feature = "console-subscriber") {
         DefaultPlugins.build().disable::<LogPlugin>()
@@ -109,13 +138,15 @@ async fn main() -> Result<()> {
                     layer().with_writer(file).boxed()
                 })
             },
+            fmt_layer: |_| Some(layer().with_writer(|| repl::LogWriter).boxed()),
             ..Default::default()
         })
     };
 
-    let builder = ClientBuilder::new_without_plugins()
+    let builder = SwarmBuilder::new_without_plugins()
         .add_plugins(default_plugins)
         .add_plugins(DefaultBotPlugins)
+        .add_plugins(DefaultSwarmPlugins)
         .add_plugins(HacksPlugin);
 
     #[cfg(feature = "replay")]
@@ -124,6 +155,12 @@ async fn main() -> Result<()> {
             if let Ok(options) = globals.get::<Table>("ReplayRecordingOptions")
                 && let Ok(path) = options.get::<String>("path")
             {
+                if globals
+                    .get::<Option<Vec<String>>>("Accounts")?
+                    .is_some_and(|accounts| accounts.len() > 1)
+                {
+                    bail!("ReplayRecordingOptions can only record a single account");
+                }
                 Some(Recorder::new(
                     path,
                     server.clone(),
@@ -137,24 +174,149 @@ async fn main() -> Result<()> {
         )),
     });
 
-    let account = if username.contains('@') {
-        Account::microsoft(&username).await?
+    let journal = if let Ok(options) = globals.get::<Table>("JournalOptions")
+        && let Ok(path) = options.get::<String>("path")
+    {
+        Some(Journal::open(&path)?)
     } else {
-        Account::offline(&username)
+        None
     };
-
-    if let AppExit::Error(code) = builder
+    let accounts = accounts(&lua, &globals, event_listeners, &bot_listeners, false).await?;
+    let lua = Arc::new(lua);
+    if args.repl {
+        repl::spawn(lua.clone())?;
+    }
+    let event_stream = EventStream::default();
+    let mut builder = builder
         .set_handler(events::handle_event)
-        .set_state(State {
-            lua: Arc::new(lua),
+        .set_swarm_handler(events::handle_swarm_event)
+        .reconnect_after(None);
+    let bots = Arc::new(
+        accounts
+            .iter()
+            .map(|(_, name, event_listeners)| (name.clone(), event_listeners.clone()))
+            .collect::<Vec<_>>(),
+    );
+    for (index, (account, name, event_listeners)) in accounts.into_iter().enumerate() {
+        let state = State {
+            name,
+            lua: lua.clone(),
+            chat: ChatQueue::default(),
             event_listeners,
-            commands: Arc::new(commands),
-        })
-        .start(account, server)
-        .await
-    {
+            commands: commands.clone(),
+            event_stream: event_stream.clone(),
+            journal: journal.clone(),
+            ticking: Arc::default(),
+            bots: bots.clone(),
+        };
+        if index == 0 {
+            builder = builder.set_swarm_state(state.clone());
+        }
+        builder = builder.add_account_with_state(account, state);
+    }
+
+    if let AppExit::Error(code) = builder.start(server).await {
         bail!("azalea exited with code {code}")
     }
 
     Ok(())
 }
+
+async fn replay_journal(
+    path: &Path,
+    lua: Lua,
+    globals: &Table,
+    event_listeners: ListenerMap,
+    bot_listeners: &BotListeners,
+) -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_writer(|| repl::LogWriter)
+        .init();
+
+    let states = accounts(&lua, globals, event_listeners, bot_listeners, true).await?;
+    let lua = Arc::new(lua);
+    let states = states
+        .into_iter()
+        .map(|(_, name, event_listeners)| State {
+            name,
+            lua: lua.clone(),
+            event_listeners,
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+    journal::replay(path, &states).await?;
+    Ok(())
+}
+
+fn register_bots(lua: &Lua, globals: &Table) -> Result<BotListeners> {
+    let bot_listeners = BotListeners::default();
+    let bots = lua.create_table()?;
+    let metatable = lua.create_table()?;
+    let m = bot_listeners.clone();
+    metatable.set(
+        "__index",
+        lua.create_function(move |lua, (bots, name): (Table, String)| {
+            let listeners = ListenerMap::default();
+            let bot = lua.create_table()?;
+            lua::events::register_globals(lua, &bot, listeners.clone())?;
+            bots.raw_set(name.as_str(), &bot)?;
+            m.lock().insert(name, listeners);
+            Ok(bot)
+        })?,
+    )?;
+    bots.set_metatable(Some(metatable))?;
+    globals.set("bots", bots)?;
+    Ok(bot_listeners)
+}
+
+async fn accounts(
+    lua: &Lua,
+    globals: &Table,
+    event_listeners: ListenerMap,
+    bot_listeners: &BotListeners,
+    offline: bool,
+) -> Result<Vec<(Account, String, ListenerMap)>> {
+    let usernames = if let Some(accounts) = globals.get::<Option<Vec<String>>>("Accounts")? {
+        accounts
+    } else {
+        vec![
+            globals
+                .get::<String>("Username")
+                .context("lua globals missing Username or Accounts variable")?,
+        ]
+    };
+
+    let mut accounts = Vec::with_capacity(usernames.len());
+    let bots = globals.get::<Table>("bots")?;
+    for username in usernames {
+        let account = if username.contains('@') && !offline {
+            Account::microsoft(&username).await?
+        } else {
+            Account::offline(&username)
+        };
+        let name = account.username().to_owned();
+
+        let existing = bot_listeners.lock().remove(&name);
+        let listeners = if accounts.is_empty() {
+            if let Some(existing) = existing {
+                event_listeners.absorb(&existing).await;
+            }
+            event_listeners.clone()
+        } else {
+            existing.unwrap_or_default()
+        };
+        let bot = match bots.raw_get::<Option<Table>>(name.as_str())? {
+            Some(bot) => bot,
+            None => lua.create_table()?,
+        };
+        lua::events::register_globals(lua, &bot, listeners.clone())?;
+        bots.raw_set(name.as_str(), bot)?;
+        accounts.push((account, name, listeners));
+    }
+    bots.set_metatable(None)?;
+
+    Ok(accounts)
+}
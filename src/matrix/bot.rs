@@ -1,21 +1,25 @@
 use std::time::Duration;
 
 use anyhow::Result;
+use azalea::brigadier::errors::BuiltInError;
+use futures::lock::Mutex;
 use log::{debug, error};
 use matrix_sdk::{
     Client, Room, RoomState,
     event_handler::Ctx,
     ruma::events::room::{
         member::StrippedRoomMemberEvent,
-        message::{MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent},
+        message::{MessageType, OriginalSyncRoomMessageEvent},
     },
 };
 use tokio::time::sleep;
 
 use super::Context;
 use crate::{
+    commands::{self, CommandSource, Origin},
     events::call_listeners,
-    lua::{eval, exec, matrix::room::Room as LuaRoom, reload},
+    lua::{Frontend, Sender, matrix::room::Room as LuaRoom},
+    permissions,
 };
 
 pub async fn on_regular_room_message(
@@ -30,50 +34,28 @@ pub async fn on_regular_room_message(
         return Ok(());
     };
 
-    if text_content.body.starts_with(&ctx.name) && ctx.is_owner(&event.sender.to_string()) {
-        let body = text_content.body[ctx.name.len()..]
-            .trim_start_matches(':')
-            .trim();
-        let split = body.split_once(char::is_whitespace).unzip();
-        let code = split
-            .1
-            .map(|body| body.trim_start_matches("```lua").trim_matches(['`', '\n']));
-
-        let mut output = None;
-        match split.0.unwrap_or(body).to_lowercase().as_str() {
-            "reload" => {
-                output = Some(
-                    reload(&ctx.state.lua, None)
-                        .map_or_else(|error| error.to_string(), |()| String::from("ok")),
-                );
-            }
-            "eval" if let Some(code) = code => {
-                output = Some(
-                    eval(&ctx.state.lua, code, None)
-                        .await
-                        .unwrap_or_else(|error| error.to_string()),
-                );
-            }
-            "exec" if let Some(code) = code => {
-                output = Some(
-                    exec(&ctx.state.lua, code, None)
-                        .await
-                        .map_or_else(|error| error.to_string(), |()| String::from("ok")),
-                );
-            }
-            "ping" => {
-                room.send(RoomMessageEventContent::text_plain("pong!"))
-                    .await?;
+    if let Some(body) = text_content.body.strip_prefix(&ctx.name)
+        && let Some(command) = to_command(body)
+    {
+        let source = || {
+            CommandSource::new(
+                ctx.state.clone(),
+                Origin::Matrix {
+                    room: room.clone(),
+                    sender: event.sender.clone(),
+                },
+            )
+        };
+        // people mention the bot by name in conversation too, so unknown commands are ignored
+        if let Err(error) = ctx.state.commands.execute(command.clone(), source().into())
+            && *error.kind() != BuiltInError::DispatcherUnknownCommand
+        {
+            let source = Mutex::new(source());
+            if let Some(reply) =
+                commands::describe_error(&ctx.state.commands, &source, &command, &error)
+            {
+                source.into_inner().reply(&reply);
             }
-            _ => (),
-        }
-
-        if let Some(output) = output {
-            room.send(RoomMessageEventContent::text_html(
-                &output,
-                format!("<pre><code>{output}</code></pre>"),
-            ))
-            .await?;
         }
     }
 
@@ -87,6 +69,34 @@ pub async fn on_regular_room_message(
     .await
 }
 
+fn to_command(body: &str) -> Option<String> {
+    if !body.starts_with(|c: char| c == ':' || c.is_whitespace()) {
+        return None;
+    }
+    let body = body.trim_start_matches(':').trim();
+    let (name, arguments) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+    if name.is_empty() {
+        return None;
+    }
+
+    let name = name.to_lowercase();
+    let arguments = arguments.trim();
+    Some(if arguments.is_empty() {
+        name
+    } else if matches!(name.as_str(), "eval" | "exec") {
+        // the rest of the line is the code, so quote it as a single argument
+        let code = arguments
+            .trim_start_matches("```lua")
+            .trim_matches(['`', '\n']);
+        format!(
+            "{name} \"{}\"",
+            code.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    } else {
+        format!("{name} {arguments}")
+    })
+}
+
 pub async fn on_stripped_state_member(
     member: StrippedRoomMemberEvent,
     client: Client,
@@ -95,7 +105,14 @@ pub async fn on_stripped_state_member(
 ) -> Result<()> {
     if let Some(user_id) = client.user_id()
         && member.state_key == user_id
-        && ctx.is_owner(&member.sender.to_string())
+        && permissions::is_trusted(
+            &ctx.state.lua,
+            &Sender {
+                name: member.sender.to_string(),
+                uuid: None,
+                frontend: Frontend::Matrix,
+            },
+        )
     {
         debug!("joining room {}", room.room_id());
         while let Err(error) = room.join().await {
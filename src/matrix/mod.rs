@@ -22,23 +22,6 @@ struct Context {
     name: String,
 }
 
-impl Context {
-    fn is_owner(&self, name: &String) -> bool {
-        self.state
-            .lua
-            .globals()
-            .get::<Table>("MatrixOptions")
-            .ok()
-            .and_then(|options| {
-                options
-                    .get::<Vec<String>>("owners")
-                    .ok()
-                    .and_then(|owners| owners.contains(name).then_some(()))
-            })
-            .is_some()
-    }
-}
-
 #[derive(Clone, Serialize, Deserialize)]
 struct Session {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+
+use mlua::{Lua, Table};
+
+use crate::lua::{Frontend, Sender};
+
+/// Returns the commands `sender` may run, with `*` meaning every command.
+pub fn allowed_commands(lua: &Lua, sender: &Sender) -> HashSet<String> {
+    let all = || HashSet::from([String::from("*")]);
+    if matches!(sender.frontend, Frontend::Console) {
+        return all();
+    }
+    let Ok(permissions) = lua.globals().get::<Table>("Permissions") else {
+        return if is_legacy_owner(lua, sender) {
+            all()
+        } else {
+            HashSet::new()
+        };
+    };
+    let Ok(roles) = permissions.get::<Table>("roles") else {
+        return HashSet::new();
+    };
+
+    granted_roles(&permissions, sender)
+        .iter()
+        .filter_map(|role| roles.get::<Vec<String>>(role.as_str()).ok())
+        .flatten()
+        .collect()
+}
+
+pub fn is_allowed(lua: &Lua, sender: &Sender, command: &str) -> bool {
+    contains(&allowed_commands(lua, sender), command)
+}
+
+pub fn contains(allowed: &HashSet<String>, command: &str) -> bool {
+    allowed.contains("*") || allowed.contains(command)
+}
+
+/// Checks whether `sender` has been granted at least one role.
+#[cfg(feature = "matrix")]
+pub fn is_trusted(lua: &Lua, sender: &Sender) -> bool {
+    lua.globals().get::<Table>("Permissions").map_or_else(
+        |_| is_legacy_owner(lua, sender),
+        |permissions| !granted_roles(&permissions, sender).is_empty(),
+    )
+}
+
+pub fn is_configured(lua: &Lua) -> bool {
+    lua.globals().get::<Table>("Permissions").is_ok()
+}
+
+fn granted_roles(permissions: &Table, sender: &Sender) -> Vec<String> {
+    match sender.frontend {
+        Frontend::Chat => sender.uuid.as_deref().and_then(|uuid| {
+            permissions
+                .get::<Table>("minecraft")
+                .and_then(|users| users.get::<Vec<String>>(uuid))
+                .ok()
+        }),
+        Frontend::Console => None,
+        Frontend::Http => permissions
+            .get::<Table>("http")
+            .and_then(|principals| principals.get::<Table>(sender.name.as_str()))
+            .and_then(|principal| principal.get::<Vec<String>>("roles"))
+            .ok(),
+        #[cfg(feature = "matrix")]
+        Frontend::Matrix => permissions
+            .get::<Table>("matrix")
+            .and_then(|users| users.get::<Vec<String>>(sender.name.as_str()))
+            .ok(),
+    }
+    .unwrap_or_default()
+}
+
+fn is_legacy_owner(lua: &Lua, sender: &Sender) -> bool {
+    let globals = lua.globals();
+    match sender.frontend {
+        Frontend::Chat => globals
+            .get::<Vec<String>>("Owners")
+            .unwrap_or_default()
+            .contains(&sender.name),
+        Frontend::Console | Frontend::Http => true,
+        #[cfg(feature = "matrix")]
+        Frontend::Matrix => globals
+            .get::<Table>("MatrixOptions")
+            .and_then(|options| options.get::<Vec<String>>("owners"))
+            .unwrap_or_default()
+            .contains(&sender.name),
+    }
+}
@@ -0,0 +1,183 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use azalea::{auto_reconnect::InternalReconnectAfter, prelude::*};
+use log::{info, warn};
+use mlua::{Table, Value};
+
+use crate::{
+    State,
+    events::{call_listeners, listener_failed},
+    lua::events::take_listeners,
+};
+
+#[derive(Component)]
+struct ReconnectAttempts(u32);
+
+#[derive(Component)]
+pub struct DisconnectRequested;
+
+struct Policy {
+    delay: f64,
+    max_delay: f64,
+    multiplier: f64,
+    max_attempts: Option<u32>,
+    jitter: f64,
+}
+
+impl Policy {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn from_globals(globals: &Table) -> Self {
+        let options = globals.get::<Table>("ReconnectOptions").ok();
+        let get = |name| {
+            options
+                .as_ref()
+                .and_then(|options| options.get::<Option<f64>>(name).ok().flatten())
+        };
+        Self {
+            delay: get("delay").unwrap_or(5_000.0).max(0.0),
+            max_delay: get("max_delay").unwrap_or(300_000.0).max(0.0),
+            multiplier: get("multiplier").unwrap_or(2.0).max(1.0),
+            max_attempts: get("max_attempts").map(|attempts| attempts as u32),
+            jitter: get("jitter").unwrap_or_default().clamp(0.0, 1.0),
+        }
+    }
+
+    fn delay_for(&self, attempt: u32) -> f64 {
+        let exponent = i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX);
+        let delay = (self.delay * self.multiplier.powi(exponent)).min(self.max_delay);
+        delay * fastrand::f64().mul_add(2.0, -1.0).mul_add(self.jitter, 1.0)
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub async fn schedule(client: Client, state: &State, reason: Option<String>) -> Result<()> {
+    let policy = Policy::from_globals(&state.lua.globals());
+    let attempt = {
+        let mut ecs = client.ecs.write();
+        let mut entity = ecs.entity_mut(client.entity);
+        if entity.take::<DisconnectRequested>().is_some() {
+            entity.remove::<ReconnectAttempts>();
+            info!("not reconnecting after a requested disconnect");
+            return Ok(());
+        }
+        let attempt = entity.get::<ReconnectAttempts>().map_or(0, |a| a.0) + 1;
+        entity.insert(ReconnectAttempts(attempt));
+        attempt
+    };
+    if policy
+        .max_attempts
+        .is_some_and(|max_attempts| attempt > max_attempts)
+    {
+        warn!("giving up on reconnecting after {} attempts", attempt - 1);
+        return Ok(());
+    }
+
+    let mut delay = policy.delay_for(attempt);
+    if let Some(listeners) = take_listeners(&state.event_listeners, "reconnecting").await {
+        let table = state.lua.create_table()?;
+        table.set("attempt", attempt)?;
+        table.set("delay", delay)?;
+        table.set("reason", reason)?;
+        for listener in listeners.iter().filter(|listener| !listener.is_disabled()) {
+            match listener.call::<Value>(&table).await {
+                Ok(Value::Boolean(false)) => {
+                    info!("reconnect vetoed by lua event listener {}", listener.id);
+                    return Ok(());
+                }
+                Ok(Value::Integer(milliseconds)) => delay = milliseconds as f64,
+                Ok(Value::Number(milliseconds)) => delay = milliseconds,
+                Ok(_) => (),
+                Err(error) => {
+                    listener_failed(
+                        &state.lua,
+                        &state.event_listeners,
+                        "reconnecting",
+                        listener,
+                        &error,
+                    )
+                    .await;
+                }
+            }
+        }
+    }
+
+    info!("reconnecting in {delay:.0}ms (attempt {attempt})");
+    client
+        .ecs
+        .write()
+        .entity_mut(client.entity)
+        .insert(InternalReconnectAfter {
+            instant: Instant::now()
+                + Duration::try_from_secs_f64(delay / 1000.0).unwrap_or_default(),
+        });
+    Ok(())
+}
+
+pub async fn finish(client: &Client, state: &State) -> Result<()> {
+    let Some(ReconnectAttempts(attempts)) = client
+        .ecs
+        .write()
+        .entity_mut(client.entity)
+        .take::<ReconnectAttempts>()
+    else {
+        return Ok(());
+    };
+
+    info!("reconnected after {attempts} attempts");
+    call_listeners(state, "reconnected", || Ok(attempts)).await
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use mlua::Lua;
+
+    use super::*;
+
+    fn policy(options: &str) -> Policy {
+        let lua = Lua::new();
+        lua.load(format!("ReconnectOptions = {options}"))
+            .exec()
+            .unwrap();
+        Policy::from_globals(&lua.globals())
+    }
+
+    #[test]
+    fn uses_defaults() {
+        let policy = Policy::from_globals(&Lua::new().globals());
+        assert_eq!(policy.delay_for(1), 5_000.0);
+        assert_eq!(policy.delay_for(2), 10_000.0);
+        assert_eq!(policy.delay_for(7), 300_000.0);
+        assert_eq!(policy.max_attempts, None);
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = policy("{ delay = 100, multiplier = 3, max_delay = 2000, max_attempts = 4 }");
+        assert_eq!(policy.delay_for(0), 100.0);
+        assert_eq!(policy.delay_for(1), 100.0);
+        assert_eq!(policy.delay_for(2), 300.0);
+        assert_eq!(policy.delay_for(3), 900.0);
+        assert_eq!(policy.delay_for(4), 2000.0);
+        assert_eq!(policy.delay_for(u32::MAX), 2000.0);
+        assert_eq!(policy.max_attempts, Some(4));
+    }
+
+    #[test]
+    fn clamps_options() {
+        let policy = policy("{ delay = -5, multiplier = 0.5, jitter = 3 }");
+        assert_eq!(policy.delay, 0.0);
+        assert_eq!(policy.multiplier, 1.0);
+        assert_eq!(policy.jitter, 1.0);
+        assert_eq!(policy.delay_for(10), 0.0);
+    }
+
+    #[test]
+    fn applies_jitter() {
+        let policy = policy("{ delay = 1000, multiplier = 1, jitter = 0.25 }");
+        for _ in 0..100 {
+            assert!((750.0..=1250.0).contains(&policy.delay_for(1)));
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::HashSet,
+    ffi::c_void,
+    fmt::Write as _,
+    io::{self, Write},
+    mem::take,
+    sync::Arc,
+    thread,
+};
+
+use anyhow::Result;
+use log::error;
+use mlua::{Error, Lua, MultiValue, Table, Value};
+use parking_lot::Mutex;
+use rustyline::{DefaultEditor, ExternalPrinter, error::ReadlineError};
+use tokio::runtime::Handle;
+
+use crate::lua::{Frontend, Sender, eval, exec};
+
+const MAX_DEPTH: usize = 8;
+
+static PRINTER: Mutex<Option<Box<dyn ExternalPrinter + Send>>> = Mutex::new(None);
+
+/// Writes log output above the REPL prompt while it is running, and to stderr otherwise.
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(printer) = PRINTER.lock().as_mut() {
+            printer
+                .print(String::from_utf8_lossy(buf).trim_end().to_owned())
+                .map_err(io::Error::other)?;
+            Ok(buf.len())
+        } else {
+            io::stderr().write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Starts reading Lua from stdin on a separate thread.
+pub fn spawn(lua: Arc<Lua>) -> Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    if let Ok(printer) = editor.create_external_printer() {
+        *PRINTER.lock() = Some(Box::new(printer));
+    }
+    let handle = Handle::current();
+    thread::spawn(move || run(&lua, &handle, editor));
+    Ok(())
+}
+
+fn run(lua: &Lua, handle: &Handle, mut editor: DefaultEditor) {
+    let mut buffer = String::new();
+    loop {
+        let line = match editor.readline(if buffer.is_empty() { "> " } else { ">> " }) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                error!("failed to read from stdin: {error}");
+                break;
+            }
+        };
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(&line);
+        if buffer.trim().is_empty() || is_incomplete(lua, &buffer) {
+            continue;
+        }
+
+        let input = take(&mut buffer);
+        let _ = editor.add_history_entry(&input);
+        let output = handle.block_on(evaluate(lua, &input));
+        if !output.is_empty() {
+            println!("{output}");
+        }
+    }
+    *PRINTER.lock() = None;
+}
+
+fn is_incomplete(lua: &Lua, input: &str) -> bool {
+    lua.load(format!("return {input}")).into_function().is_err()
+        && matches!(
+            lua.load(input).into_function(),
+            Err(Error::SyntaxError {
+                incomplete_input: true,
+                ..
+            })
+        )
+}
+
+async fn evaluate(lua: &Lua, input: &str) -> String {
+    let sender = Some(Sender {
+        name: String::from("console"),
+        uuid: None,
+        frontend: Frontend::Console,
+    });
+    let expression = format!("return {input}");
+    if lua.load(&expression).into_function().is_ok() {
+        eval::<MultiValue>(lua, &expression, sender)
+            .await
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|value| pretty(value, 0, &mut HashSet::new()))
+                    .collect::<Vec<_>>()
+                    .join("\t")
+            })
+    } else {
+        exec(lua, input, sender).await.map(|()| String::new())
+    }
+    .unwrap_or_else(|error| error.to_string())
+}
+
+fn pretty(value: &Value, depth: usize, seen: &mut HashSet<*const c_void>) -> String {
+    match value {
+        Value::String(string) => format!("{:?}", string.to_string_lossy()),
+        Value::Table(table) => pretty_table(table, depth, seen),
+        other => other
+            .to_string()
+            .unwrap_or_else(|_| other.type_name().to_owned()),
+    }
+}
+
+fn pretty_table(table: &Table, depth: usize, seen: &mut HashSet<*const c_void>) -> String {
+    if depth >= MAX_DEPTH || !seen.insert(table.to_pointer()) {
+        return format!("<table: {:?}>", table.to_pointer());
+    }
+
+    let indent = "  ".repeat(depth + 1);
+    let mut output = String::from("{\n");
+    for (key, value) in table.pairs::<Value, Value>().filter_map(Result::ok) {
+        let key = match key {
+            Value::String(ref string) => string.to_string_lossy(),
+            other => format!("[{}]", pretty(&other, depth + 1, seen)),
+        };
+        let _ = writeln!(
+            output,
+            "{indent}{key} = {},",
+            pretty(&value, depth + 1, seen)
+        );
+    }
+    seen.remove(&table.to_pointer());
+
+    if output.len() == 2 {
+        String::from("{}")
+    } else {
+        output.push_str(&"  ".repeat(depth));
+        output.push('}');
+        output
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashSet,
+    fs::canonicalize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::{debug, error, info, warn};
+use mlua::{Function, Lua, Table, Value};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher, recommended_watcher};
+use tokio::{sync::mpsc, time::timeout};
+
+use crate::{State, events::call_listeners, lua::reload};
+
+const DEFAULT_DEBOUNCE: u64 = 250;
+
+#[derive(Default)]
+struct Targets {
+    files: HashSet<PathBuf>,
+    directories: HashSet<PathBuf>,
+    watched: HashSet<PathBuf>,
+}
+
+impl Targets {
+    fn update(&mut self, lua: &Lua, options: &Table, watcher: &mut RecommendedWatcher) {
+        for path in options.get::<Vec<String>>("paths").unwrap_or_default() {
+            if let Ok(path) = canonicalize(path) {
+                if path.is_dir() {
+                    self.watch(watcher, &path, RecursiveMode::Recursive);
+                    self.directories.insert(path);
+                } else {
+                    self.add_file(watcher, path);
+                }
+            }
+        }
+
+        let globals = lua.globals();
+        if let Ok(path) = globals.get::<String>("SCRIPT_PATH")
+            && let Ok(path) = canonicalize(path)
+        {
+            self.add_file(watcher, path);
+        }
+        if let Ok(package) = globals.get::<Table>("package")
+            && let Ok(loaded) = package.get::<Table>("loaded")
+            && let Ok(searchpath) = package.get::<Function>("searchpath")
+            && let Ok(search) = package.get::<String>("path")
+        {
+            for (name, _) in loaded.pairs::<String, Value>().filter_map(Result::ok) {
+                if let Ok(Some(path)) = searchpath.call::<Option<String>>((name, search.as_str()))
+                    && let Ok(path) = canonicalize(path)
+                {
+                    self.add_file(watcher, path);
+                }
+            }
+        }
+    }
+
+    fn add_file(&mut self, watcher: &mut RecommendedWatcher, path: PathBuf) {
+        if let Some(parent) = path.parent() {
+            self.watch(watcher, parent, RecursiveMode::NonRecursive);
+        }
+        self.files.insert(path);
+    }
+
+    fn watch(&mut self, watcher: &mut RecommendedWatcher, path: &Path, mode: RecursiveMode) {
+        if !self.watched.contains(path) {
+            if let Err(error) = watcher.watch(path, mode) {
+                warn!("failed to watch {}: {error}", path.display());
+            } else {
+                debug!("watching {} for script changes", path.display());
+                self.watched.insert(path.to_owned());
+            }
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        self.files.contains(path)
+            || (path.extension().is_some_and(|extension| extension == "lua")
+                && self
+                    .directories
+                    .iter()
+                    .any(|directory| path.starts_with(directory)))
+    }
+}
+
+/// Reloads the script whenever it or one of its modules changes, if `WatchOptions` is set.
+pub async fn watch(state: State) -> Result<()> {
+    let Ok(options) = state.lua.globals().get::<Table>("WatchOptions") else {
+        return Ok(());
+    };
+    let debounce = Duration::from_millis(options.get("debounce").unwrap_or(DEFAULT_DEBOUNCE));
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = recommended_watcher(move |result: notify::Result<Event>| match result {
+        Ok(event) if !event.kind.is_access() => {
+            let _ = sender.send(event.paths);
+        }
+        Ok(_) => (),
+        Err(error) => warn!("script watcher error: {error}"),
+    })?;
+    let mut targets = Targets::default();
+    targets.update(&state.lua, &options, &mut watcher);
+
+    while let Some(paths) = receiver.recv().await {
+        if !paths.iter().any(|path| targets.matches(path)) {
+            continue;
+        }
+        while let Ok(Some(_)) = timeout(debounce, receiver.recv()).await {}
+
+        info!("reloading after script change");
+        if let Err(error) = reload(&state.lua, None) {
+            error!("failed to reload: {error}");
+            call_listeners(&state, "reload_failed", || Ok(error.to_string())).await?;
+        }
+        targets.update(&state.lua, &options, &mut watcher);
+    }
+
+    Ok(())
+}